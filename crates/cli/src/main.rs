@@ -1,23 +1,59 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
 use clap::{Args, Parser, Subcommand};
 use driver::{
-    chroma::ExtendedMatrixEffect,
-    common::{NormalPollingRate, RAZER_USB_VENDOR_ID},
+    capture::{read_capture, ReportDirection},
+    chroma::{ExtendedMatrixEffect, LedId},
+    common::{
+        DeviceMode, Dpi, DpiStages, Persistence, PollingRateFamily, RAZER_MOUSE_MAX_DPI_STAGES,
+        RAZER_USB_VENDOR_ID,
+    },
     devices::{RazerDevice, RazerDeviceClaimed},
+    dissector::{dissect_report, parse_hex},
+    simulator::SimulatedMouse,
 };
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Cli {
+    /// Run against a simulated DeathAdder V2 Pro instead of a connected mouse
+    #[arg(long, global = true)]
+    simulate: bool,
+    /// Append every report exchanged with the mouse to this JSONL file
+    #[arg(long, global = true)]
+    record: Option<PathBuf>,
+    /// Give up on a command if the mouse doesn't answer within this many milliseconds
+    #[arg(long, global = true)]
+    timeout: Option<u64>,
+    /// Save DPI, DPI stages and lighting to the mouse's onboard memory, and read them back
+    /// from there, instead of only what the mouse uses until it's power cycled
+    #[arg(long, global = true)]
+    persist: bool,
     #[command(subcommand)]
     command: Command,
 }
 
 #[derive(Subcommand, Debug)]
 enum Command {
+    /// Extract and decode the Razer reports in a usbmon or USBPcap capture (no device needed)
+    Capture {
+        path: PathBuf,
+    },
+    /// Pretty-print a raw 90 byte Razer report given in hex (no device needed)
+    Decode {
+        hex: Vec<String>,
+    },
     Dpi(DpiCommand),
     Info,
     Led(LedCommand),
+    /// Switch between normal mode and driver mode, where host software drives the mouse
+    Mode(ModeCommand),
     PollingRate(PollingRateCommand),
+    /// Sleep timer and low battery threshold of a wireless mouse
+    Power(PowerCommand),
 }
 
 #[derive(Parser, Debug)]
@@ -29,13 +65,32 @@ struct DpiCommand {
 #[derive(Subcommand, Debug)]
 enum DpiAction {
     Get,
-    Set { dpi: u16 }, // TODO: Support x/y DPI separately
+    /// DPI on both axes, or XxY (ex: 1600x800) to set them separately
+    Set {
+        dpi: Dpi,
+    },
     GetStages,
-    SetStages { dpis: Vec<u16> },
+    /// Replace the DPI stages, each one X or XxY (ex: 400 800 1600x800)
+    SetStages {
+        /// Stage to switch to, from 1
+        #[arg(short, long, default_value_t = 1)]
+        active: u8,
+        #[arg(required = true)]
+        dpis: Vec<Dpi>,
+    },
+    /// Switch to another DPI stage, from 1
+    SetActiveStage {
+        stage: u8,
+    },
+    /// Switch to the next DPI stage, wrapping around after the last one
+    NextStage,
+    /// Switch to the previous DPI stage, wrapping around before the first one
+    PrevStage,
 }
 
 #[derive(Args, Debug)]
 struct LedCommand {
+    /// LED to control, the first one `led list` prints if omitted
     #[arg(short, long)]
     led: Option<Led>,
     #[command(subcommand)]
@@ -44,6 +99,10 @@ struct LedCommand {
 
 #[derive(Subcommand, Clone, Debug)]
 enum LedEffect {
+    /// The LEDs the mouse has
+    List,
+    /// Print the effect and brightness
+    Get,
     Off,
     Static {
         #[arg(short, long)]
@@ -58,6 +117,25 @@ enum LedEffect {
         #[arg(short, long)]
         speed: u8,
     },
+    /// Colors sweeping across the LEDs
+    Wave {
+        #[arg(short, long, default_value = "right")]
+        direction: WaveDirection,
+        /// From 1 to 255, lower is faster
+        #[arg(short, long, default_value_t = 0x28)]
+        speed: u8,
+    },
+    /// LEDs twinkling on and off
+    Starlight(StarlightCommand),
+    /// Colors spinning around the LEDs
+    Wheel {
+        #[arg(short, long, default_value = "clockwise")]
+        direction: WheelDirection,
+    },
+    /// Print the brightness, or set it from 0 (off) to 255
+    Brightness {
+        brightness: Option<u8>,
+    },
 }
 
 #[derive(Subcommand, Clone, Debug)]
@@ -67,9 +145,104 @@ enum BreathingEffect {
     Dual { color1: String, color2: String },
 }
 
-#[derive(clap::ValueEnum, Clone, Debug)]
+#[derive(Args, Clone, Debug)]
+struct StarlightCommand {
+    /// From 1 to 3
+    #[arg(short, long, default_value_t = 2)]
+    speed: u8,
+    #[command(subcommand)]
+    colors: StarlightColors,
+}
+
+#[derive(Subcommand, Clone, Debug)]
+enum StarlightColors {
+    Random,
+    Single { color: String },
+    Dual { color1: String, color2: String },
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum WaveDirection {
+    Left,
+    Right,
+}
+
+impl From<WaveDirection> for driver::chroma::WaveDirection {
+    fn from(direction: WaveDirection) -> Self {
+        match direction {
+            WaveDirection::Left => driver::chroma::WaveDirection::Left,
+            WaveDirection::Right => driver::chroma::WaveDirection::Right,
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum WheelDirection {
+    Clockwise,
+    CounterClockwise,
+}
+
+impl From<WheelDirection> for driver::chroma::WheelDirection {
+    fn from(direction: WheelDirection) -> Self {
+        match direction {
+            WheelDirection::Clockwise => driver::chroma::WheelDirection::Clockwise,
+            WheelDirection::CounterClockwise => driver::chroma::WheelDirection::CounterClockwise,
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
 enum Led {
+    ScrollWheel,
+    Battery,
     Logo,
+    Backlight,
+    Macro,
+    Game,
+    RightSide,
+    LeftSide,
+}
+
+impl From<Led> for LedId {
+    fn from(led: Led) -> Self {
+        match led {
+            Led::ScrollWheel => LedId::ScrollWheel,
+            Led::Battery => LedId::Battery,
+            Led::Logo => LedId::Logo,
+            Led::Backlight => LedId::Backlight,
+            Led::Macro => LedId::Macro,
+            Led::Game => LedId::Game,
+            Led::RightSide => LedId::RightSide,
+            Led::LeftSide => LedId::LeftSide,
+        }
+    }
+}
+
+#[derive(Parser, Debug)]
+struct ModeCommand {
+    #[command(subcommand)]
+    command: Option<ModeAction>,
+}
+
+#[derive(Subcommand, Debug)]
+enum ModeAction {
+    Get,
+    Set { mode: Mode },
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum Mode {
+    Normal,
+    Driver,
+}
+
+impl From<Mode> for DeviceMode {
+    fn from(mode: Mode) -> Self {
+        match mode {
+            Mode::Normal => DeviceMode::Normal,
+            Mode::Driver => DeviceMode::Driver,
+        }
+    }
 }
 
 #[derive(Parser, Debug)]
@@ -81,39 +254,130 @@ struct PollingRateCommand {
 #[derive(Subcommand, Debug)]
 enum PollingRateAction {
     Get,
-    Set { value: u16 },
+    /// Polling rate in Hz, one of those listed by `polling-rate list`
+    Set {
+        value: u16,
+    },
+    /// The polling rates the mouse supports
+    List,
+}
+
+#[derive(Parser, Debug)]
+struct PowerCommand {
+    #[command(subcommand)]
+    command: Option<PowerAction>,
+}
+
+#[derive(Subcommand, Debug)]
+enum PowerAction {
+    Get,
+    /// Seconds of inactivity before the mouse goes to sleep
+    SetIdleTime {
+        seconds: u64,
+    },
+    /// Battery percentage below which the mouse starts saving power
+    SetLowBatteryThreshold {
+        percent: u8,
+    },
 }
 
 #[tokio::main]
 async fn main() {
     let args = Cli::parse();
 
-    let mouse_info = nusb::list_devices()
-        .unwrap()
-        .find(|device_info| device_info.vendor_id() == RAZER_USB_VENDOR_ID)
-        .unwrap();
-    let mouse = RazerDevice::new(mouse_info.clone()).claim().unwrap();
+    match &args.command {
+        Command::Capture { path } => return handle_capture_command(path),
+        Command::Decode { hex } => return handle_decode_command(hex),
+        _ => {}
+    }
+
+    let device = if args.simulate {
+        RazerDevice::simulated(SimulatedMouse::default())
+    } else {
+        let mouse_info = nusb::list_devices()
+            .unwrap()
+            .find(|device_info| device_info.vendor_id() == RAZER_USB_VENDOR_ID)
+            .unwrap();
+        RazerDevice::new(mouse_info)
+    };
+    let device = match args.record {
+        Some(path) => device.with_recording(path),
+        None => device,
+    };
+    let device = match args.timeout {
+        Some(timeout) => device.with_timeout(Duration::from_millis(timeout)),
+        None => device,
+    };
+    let mouse = device.claim().unwrap();
 
-    let device_name = mouse_info.product_string().unwrap_or("Unknown device");
+    let device_name = device.name().unwrap_or("Unknown device");
     println!("{}", device_name);
 
-    handle_command(mouse, args.command).await;
+    let persistence = if args.persist {
+        Persistence::OnboardMemory
+    } else {
+        Persistence::Temporary
+    };
+    handle_command(&mouse, args.command, persistence).await;
+
+    if let Err(err) = mouse.release().await {
+        println!(
+            "Failed to give the mouse back to the kernel driver: {}",
+            err
+        );
+    }
 }
 
-async fn handle_command(mouse: RazerDeviceClaimed, command: Command) {
+async fn handle_command(mouse: &RazerDeviceClaimed, command: Command, persistence: Persistence) {
     match command {
-        Command::Dpi(command) => handle_dpi_command(&mouse, command).await,
-        Command::Info => handle_info_command(&mouse).await,
-        Command::Led(command) => handle_led_command(&mouse, command).await,
+        Command::Capture { .. } | Command::Decode { .. } => {
+            unreachable!("Handled before claiming a device")
+        }
+        Command::Dpi(command) => handle_dpi_command(mouse, command, persistence).await,
+        Command::Info => handle_info_command(mouse).await,
+        Command::Led(command) => handle_led_command(mouse, command, persistence).await,
+        Command::Mode(command) => handle_mode_command(mouse, command).await,
         Command::PollingRate(command) => handle_polling_rate_command(mouse, command).await,
+        Command::Power(command) => handle_power_command(mouse, command).await,
     }
 }
 
-async fn handle_led_command(mouse: &RazerDeviceClaimed, command: LedCommand) {
+async fn handle_led_command(
+    mouse: &RazerDeviceClaimed,
+    command: LedCommand,
+    persistence: Persistence,
+) {
+    if let LedEffect::List = command.effect {
+        for led in mouse.get_leds() {
+            println!("{}", led);
+        }
+        return;
+    }
+    let led = command
+        .led
+        .map(LedId::from)
+        .or_else(|| mouse.get_leds().first().copied());
+    let Some(led) = led else {
+        println!("{}", driver::Error::Unsupported);
+        return;
+    };
     match command.effect {
+        LedEffect::List => unreachable!("Handled before resolving the LED"),
+        LedEffect::Get => {
+            let effect = mouse
+                .get_chroma_matrix_effect(led, persistence)
+                .await
+                .map_or_else(|err| err.to_string(), |effect| effect.to_string());
+            println!("Effect: {}", effect);
+            let brightness = mouse
+                .get_led_brightness(led, persistence)
+                .await
+                .map_or_else(|err| err.to_string(), |brightness| brightness.to_string());
+            println!("Brightness: {}", brightness);
+        }
         LedEffect::Off => {
             let result = mouse
-                .chroma_logo_matrix_effect(ExtendedMatrixEffect::None)
+                .chroma_matrix_effect(led, ExtendedMatrixEffect::None, persistence)
                 .await;
             if let Err(err) = result {
                 println!("{}", err);
@@ -124,7 +388,7 @@ async fn handle_led_command(mouse: &RazerDeviceClaimed, command: LedCommand) {
             match color {
                 Ok(color) => {
                     let result = mouse
-                        .chroma_logo_matrix_effect(ExtendedMatrixEffect::Static(color))
+                        .chroma_matrix_effect(led, ExtendedMatrixEffect::Static(color), persistence)
                         .await;
                     if let Err(err) = result {
                         println!("{}", err);
@@ -166,14 +430,14 @@ async fn handle_led_command(mouse: &RazerDeviceClaimed, command: LedCommand) {
                     }
                 }
             };
-            let result = mouse.chroma_logo_matrix_effect(effect).await;
+            let result = mouse.chroma_matrix_effect(led, effect, persistence).await;
             if let Err(err) = result {
                 println!("{}", err);
             }
         }
         LedEffect::Spectrum => {
             let result = mouse
-                .chroma_logo_matrix_effect(ExtendedMatrixEffect::Spectrum)
+                .chroma_matrix_effect(led, ExtendedMatrixEffect::Spectrum, persistence)
                 .await;
             if let Err(err) = result {
                 println!("{}", err);
@@ -184,7 +448,11 @@ async fn handle_led_command(mouse: &RazerDeviceClaimed, command: LedCommand) {
             match color {
                 Ok(color) => {
                     let result = mouse
-                        .chroma_logo_matrix_effect(ExtendedMatrixEffect::Reactive(color, speed))
+                        .chroma_matrix_effect(
+                            led,
+                            ExtendedMatrixEffect::Reactive(color, speed),
+                            persistence,
+                        )
                         .await;
                     if let Err(err) = result {
                         println!("{}", err);
@@ -195,36 +463,269 @@ async fn handle_led_command(mouse: &RazerDeviceClaimed, command: LedCommand) {
                 }
             }
         }
+        LedEffect::Wave { direction, speed } => {
+            let effect = ExtendedMatrixEffect::Wave(direction.into(), speed);
+            let result = mouse.chroma_matrix_effect(led, effect, persistence).await;
+            if let Err(err) = result {
+                println!("{}", err);
+            }
+        }
+        LedEffect::Starlight(starlight) => {
+            let colors = match starlight.colors {
+                StarlightColors::Random => driver::chroma::StarlightEffect::Random,
+                StarlightColors::Single { color } => match color.parse() {
+                    Ok(color) => driver::chroma::StarlightEffect::Single(color),
+                    Err(_) => {
+                        println!("{}", color_err_msg());
+                        return;
+                    }
+                },
+                StarlightColors::Dual { color1, color2 } => {
+                    match (color1.parse(), color2.parse()) {
+                        (Ok(color1), Ok(color2)) => {
+                            driver::chroma::StarlightEffect::Dual(color1, color2)
+                        }
+                        _ => {
+                            println!("{}", color_err_msg());
+                            return;
+                        }
+                    }
+                }
+            };
+            let effect = ExtendedMatrixEffect::Starlight(colors, starlight.speed);
+            let result = mouse.chroma_matrix_effect(led, effect, persistence).await;
+            if let Err(err) = result {
+                println!("{}", err);
+            }
+        }
+        LedEffect::Wheel { direction } => {
+            let effect = ExtendedMatrixEffect::Wheel(direction.into());
+            let result = mouse.chroma_matrix_effect(led, effect, persistence).await;
+            if let Err(err) = result {
+                println!("{}", err);
+            }
+        }
+        LedEffect::Brightness { brightness: None } => {
+            match mouse.get_led_brightness(led, persistence).await {
+                Ok(brightness) => println!("Brightness: {}", brightness),
+                Err(err) => println!("{}", err),
+            }
+        }
+        LedEffect::Brightness {
+            brightness: Some(brightness),
+        } => {
+            let result = mouse.set_led_brightness(led, brightness, persistence).await;
+            if let Err(err) = result {
+                println!("{}", err);
+            }
+        }
+    }
+}
+
+fn handle_capture_command(path: &Path) {
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(err) => {
+            eprintln!("{}: {}", path.display(), err);
+            std::process::exit(1);
+        }
+    };
+    let reports = match read_capture(&data) {
+        Ok(reports) => reports,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let mut failed = false;
+    for report in reports {
+        let direction = match report.direction {
+            ReportDirection::SetReport => "SET_REPORT",
+            ReportDirection::GetReport => "GET_REPORT",
+        };
+        println!(
+            "[{:.6}] {:03}:{:03} {}",
+            report.timestamp.as_secs_f64(),
+            report.bus,
+            report.device,
+            direction
+        );
+        match dissect_report(&report.report) {
+            Ok(dissection) => println!("{}\n", dissection),
+            Err(err) => {
+                eprintln!("{}\n", err);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        std::process::exit(1);
     }
 }
 
-async fn handle_dpi_command(mouse: &RazerDeviceClaimed, dpi_command: DpiCommand) {
+fn handle_decode_command(hex: &[String]) {
+    let result = parse_hex(&hex.join(" ")).and_then(|report| dissect_report(&report));
+    match result {
+        Ok(dissection) => println!("{}", dissection),
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+async fn handle_dpi_command(
+    mouse: &RazerDeviceClaimed,
+    dpi_command: DpiCommand,
+    persistence: Persistence,
+) {
     match dpi_command.command {
         Some(DpiAction::Get) | None => {
             let dpi = mouse
-                .get_dpi()
+                .get_dpi(persistence)
                 .await
-                .map_or_else(|err| err.to_string(), |dpi| format!("DPI: {:?}", dpi));
+                .map_or_else(|err| err.to_string(), |dpi| format!("DPI: {}", dpi));
             println!("{}", dpi);
         }
         Some(DpiAction::Set { dpi }) => {
-            let result = mouse.set_dpi(dpi.into()).await;
+            let result = mouse.set_dpi(dpi, persistence).await;
             if let Err(err) = result {
                 println!("{}", err);
             }
         }
-        Some(DpiAction::GetStages) => {
-            let dpi_stages = mouse.get_dpi_stages().await.map_or_else(
-                |err| err.to_string(),
-                |dpi_stages| format!("{:?}", dpi_stages),
-            );
-            println!("{}", dpi_stages);
+        Some(DpiAction::GetStages) => match mouse.get_dpi_stages(persistence).await {
+            Ok(dpi_stages) => print_dpi_stages(&dpi_stages),
+            Err(err) => println!("{}", err),
+        },
+        Some(DpiAction::SetStages { active, dpis }) => {
+            let dpi_stages = match new_dpi_stages(mouse, active, dpis) {
+                Ok(dpi_stages) => dpi_stages,
+                Err(msg) => {
+                    println!("{}", msg);
+                    return;
+                }
+            };
+            set_dpi_stages(mouse, &dpi_stages, persistence).await;
+        }
+        Some(DpiAction::SetActiveStage { stage }) => {
+            switch_dpi_stage(mouse, persistence, |_, _| stage.checked_sub(1)).await
+        }
+        Some(DpiAction::NextStage) => {
+            switch_dpi_stage(mouse, persistence, |active, count| {
+                Some((active + 1) % count)
+            })
+            .await
+        }
+        Some(DpiAction::PrevStage) => {
+            switch_dpi_stage(mouse, persistence, |active, count| {
+                Some((active + count - 1) % count)
+            })
+            .await
+        }
+    }
+}
+
+/// Validate stages given on the command line, `active` counting from 1
+fn new_dpi_stages(
+    mouse: &RazerDeviceClaimed,
+    active: u8,
+    dpis: Vec<Dpi>,
+) -> Result<DpiStages, String> {
+    if dpis.len() > RAZER_MOUSE_MAX_DPI_STAGES as usize {
+        return Err(format!(
+            "Too many DPI stages, the mouse has at most {}",
+            RAZER_MOUSE_MAX_DPI_STAGES
+        ));
+    }
+    if active == 0 || active as usize > dpis.len() {
+        return Err(format!(
+            "Invalid active stage {}, must be between 1 and {}",
+            active,
+            dpis.len()
+        ));
+    }
+    let (min_dpi, max_dpi) = mouse.get_dpi_range();
+    for (index, dpi) in dpis.iter().enumerate() {
+        if [dpi.x, dpi.y]
+            .iter()
+            .any(|&axis| axis < min_dpi || axis > max_dpi)
+        {
+            return Err(format!(
+                "Stage {}: {} DPI is outside the mouse's range of {} to {}",
+                index + 1,
+                dpi,
+                min_dpi,
+                max_dpi
+            ));
+        }
+    }
+    DpiStages::new(active - 1, dpis).map_err(|err| err.to_string())
+}
+
+/// Switch to the stage `next(active, number of stages)` returns, both 0-based
+async fn switch_dpi_stage(
+    mouse: &RazerDeviceClaimed,
+    persistence: Persistence,
+    next: impl FnOnce(u8, u8) -> Option<u8>,
+) {
+    let dpi_stages = match mouse.get_dpi_stages(persistence).await {
+        Ok(dpi_stages) => dpi_stages,
+        Err(err) => {
+            println!("{}", err);
+            return;
         }
-        Some(DpiAction::SetStages { dpis: _ }) => todo!(),
+    };
+    let count = dpi_stages.stages().len() as u8;
+    let dpi_stages = next(dpi_stages.active(), count)
+        .filter(|&active| active < count)
+        .and_then(|active| dpi_stages.with_active(active).ok());
+    match dpi_stages {
+        Some(dpi_stages) => set_dpi_stages(mouse, &dpi_stages, persistence).await,
+        None => println!("Invalid stage, must be between 1 and {}", count),
+    }
+}
+
+/// Set the stages, then print them as the mouse reports them back
+async fn set_dpi_stages(
+    mouse: &RazerDeviceClaimed,
+    dpi_stages: &DpiStages,
+    persistence: Persistence,
+) {
+    if let Err(err) = mouse.set_dpi_stages(dpi_stages, persistence).await {
+        println!("{}", err);
+        return;
+    }
+    match mouse.get_dpi_stages(persistence).await {
+        Ok(dpi_stages) => print_dpi_stages(&dpi_stages),
+        Err(err) => println!("{}", err),
+    }
+}
+
+/// One row per stage, the active one marked with a `*`
+fn print_dpi_stages(dpi_stages: &DpiStages) {
+    println!("  Stage      X      Y");
+    for (index, dpi) in dpi_stages.stages().iter().enumerate() {
+        let marker = if index == dpi_stages.active() as usize {
+            '*'
+        } else {
+            ' '
+        };
+        println!("{} {:>5}  {:>5}  {:>5}", marker, index + 1, dpi.x, dpi.y);
     }
 }
 
 async fn handle_info_command(mouse: &RazerDeviceClaimed) {
+    let firmware_version = mouse.get_firmware_version().await.map_or_else(
+        |err| err.to_string(),
+        |firmware_version| firmware_version.to_string(),
+    );
+    println!("Firmware: {}", firmware_version);
+    let serial = mouse
+        .get_serial()
+        .await
+        .unwrap_or_else(|err| err.to_string());
+    println!("Serial: {}", serial);
     let battery_level = mouse.get_battery_level().await.map_or_else(
         |err| err.to_string(),
         |battery_level| battery_level.to_string(),
@@ -232,7 +733,25 @@ async fn handle_info_command(mouse: &RazerDeviceClaimed) {
     println!("Battery Level: {}", battery_level);
 }
 
-async fn handle_polling_rate_command(mouse: RazerDeviceClaimed, command: PollingRateCommand) {
+async fn handle_mode_command(mouse: &RazerDeviceClaimed, command: ModeCommand) {
+    match command.command {
+        Some(ModeAction::Get) | None => {
+            let mode = mouse
+                .get_device_mode()
+                .await
+                .map_or_else(|err| err.to_string(), |mode| format!("{:?}", mode));
+            println!("Mode: {}", mode);
+        }
+        Some(ModeAction::Set { mode }) => {
+            let result = mouse.set_device_mode(mode.into()).await;
+            if let Err(err) = result {
+                println!("{}", err);
+            }
+        }
+    }
+}
+
+async fn handle_polling_rate_command(mouse: &RazerDeviceClaimed, command: PollingRateCommand) {
     match command.command {
         Some(PollingRateAction::Get) | None => {
             let polling_rate = mouse.get_polling_rate().await.map_or_else(
@@ -242,20 +761,63 @@ async fn handle_polling_rate_command(mouse: RazerDeviceClaimed, command: Polling
             println!("Polling Rate: {}", polling_rate)
         }
         Some(PollingRateAction::Set { value }) => {
-            // TODO: support extended polling rates
-            let polling_rate = NormalPollingRate::try_from(value);
-            match polling_rate {
-                Ok(polling_rate) => {
-                    let result = mouse.set_polling_rate(polling_rate.into()).await;
+            let Some(family) = mouse.get_polling_rate_family() else {
+                println!("{}", driver::Error::Unsupported);
+                return;
+            };
+            match family.rate(value) {
+                Some(polling_rate) => {
+                    let result = mouse.set_polling_rate(polling_rate).await;
                     if let Err(err) = result {
                         println!("{}", err);
                     }
                 }
-                Err(_) => {
-                    println!("Invalid polling rate. Must be one of: [125, 500, 1000]");
+                None => {
+                    println!(
+                        "Invalid polling rate. Must be one of: [{}]",
+                        polling_rates(family)
+                    );
                 }
             }
         }
+        Some(PollingRateAction::List) => match mouse.get_polling_rate_family() {
+            Some(family) => println!("Polling Rates: {}", polling_rates(family)),
+            None => println!("{}", driver::Error::Unsupported),
+        },
+    }
+}
+
+fn polling_rates(family: PollingRateFamily) -> String {
+    let rates: Vec<String> = family.rates().iter().map(|rate| rate.to_string()).collect();
+    rates.join(", ")
+}
+
+async fn handle_power_command(mouse: &RazerDeviceClaimed, command: PowerCommand) {
+    match command.command {
+        Some(PowerAction::Get) | None => {
+            let idle_time = mouse.get_idle_time().await.map_or_else(
+                |err| err.to_string(),
+                |idle_time| format!("{}s", idle_time.as_secs()),
+            );
+            println!("Idle Time: {}", idle_time);
+            let low_battery_threshold = mouse
+                .get_low_battery_threshold()
+                .await
+                .map_or_else(|err| err.to_string(), |threshold| format!("{}%", threshold));
+            println!("Low Battery Threshold: {}", low_battery_threshold);
+        }
+        Some(PowerAction::SetIdleTime { seconds }) => {
+            let result = mouse.set_idle_time(Duration::from_secs(seconds)).await;
+            if let Err(err) = result {
+                println!("{}", err);
+            }
+        }
+        Some(PowerAction::SetLowBatteryThreshold { percent }) => {
+            let result = mouse.set_low_battery_threshold(percent).await;
+            if let Err(err) = result {
+                println!("{}", err);
+            }
+        }
     }
 }
 
@@ -1,64 +1,127 @@
+use std::{collections::BTreeMap, time::Duration};
+
 use crate::{
-    common::{Dpi, DpiStages, PollingRate, RAZER_MOUSE_WAIT_TIME},
+    chroma::{ExtendedMatrixEffect, LedId},
+    common::{Dpi, DpiStages, FirmwareVersion, Persistence, PollingRate, PollingRateFamily},
     devices::FeatureSet,
+    Result,
 };
 
 #[derive(Clone, Debug, Default)]
 pub struct DeviceInfo {
+    pub firmware_version: Option<FirmwareVersion>,
+    pub serial: Option<String>,
     pub dpi: Option<Dpi>,
     pub dpi_range: (u16, u16),
     pub dpi_stages: Option<DpiStages>,
     pub polling_rate: Option<PollingRate>,
+    pub polling_rate_family: Option<PollingRateFamily>,
     pub battery_level: Option<f32>,
     pub charging_status: Option<bool>,
+    pub idle_time: Option<Duration>,
+    /// Percent
+    pub low_battery_threshold: Option<u8>,
+    /// The LEDs the device has, in the order to show them
+    pub leds: Vec<LedId>,
+    /// Effect of each LED that reported one
+    pub led_effects: BTreeMap<LedId, ExtendedMatrixEffect>,
+    /// Brightness of each LED that reported one, 0-255
+    pub led_brightness: BTreeMap<LedId, u8>,
 }
 
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct DeviceSettings {
+    /// Whether the DPI, DPI stages and lighting are saved to the mouse's onboard memory, by
+    /// default they only last until it's power cycled
+    pub persistence: Persistence,
     pub dpi: Option<Dpi>,
     pub dpi_stages: Option<DpiStages>,
     pub polling_rate: Option<PollingRate>,
+    pub idle_time: Option<Duration>,
+    /// Percent
+    pub low_battery_threshold: Option<u8>,
+    pub led_effects: BTreeMap<LedId, ExtendedMatrixEffect>,
+    /// 0-255
+    pub led_brightness: BTreeMap<LedId, u8>,
 }
 
 #[allow(async_fn_in_trait)]
 pub trait BatchedFeatureSet {
-    async fn get_batched(&self) -> DeviceInfo;
-    async fn set_batched(&self, settings: &DeviceSettings) -> anyhow::Result<()>;
+    /// Read everything the device reports, with the DPI stages and lighting from `persistence`
+    /// so what was just written with `DeviceSettings::persistence` reads back
+    async fn get_batched(&self, persistence: Persistence) -> DeviceInfo;
+    async fn set_batched(&self, settings: &DeviceSettings) -> Result<()>;
 }
 
 impl BatchedFeatureSet for dyn FeatureSet {
-    async fn get_batched(&self) -> DeviceInfo {
-        // Sometimes the device returns garbage info (like DPI of 0) if set_batched() or get_batched() are called in quick succession
-        tokio::time::sleep(RAZER_MOUSE_WAIT_TIME).await;
-        let dpi = self.get_dpi().await;
+    async fn get_batched(&self, persistence: Persistence) -> DeviceInfo {
+        let firmware_version = self.get_firmware_version().await;
+        let serial = self.get_serial().await;
+        // What the mouse uses right now, which may not be saved
+        let dpi = self.get_dpi(Persistence::Temporary).await;
         let dpi_range = self.get_dpi_range();
-        let dpi_stages = self.get_dpi_stages().await;
+        let dpi_stages = self.get_dpi_stages(persistence).await;
         let polling_rate = self.get_polling_rate().await;
+        let polling_rate_family = self.get_polling_rate_family();
         let battery_level = self.get_battery_level().await;
         let charging_status = self.get_charging_status().await;
+        let idle_time = self.get_idle_time().await;
+        let low_battery_threshold = self.get_low_battery_threshold().await;
+        let leds = self.get_leds().to_vec();
+        let mut led_effects = BTreeMap::new();
+        let mut led_brightness = BTreeMap::new();
+        for &led in &leds {
+            if let Ok(effect) = self.get_chroma_matrix_effect(led, persistence).await {
+                led_effects.insert(led, effect);
+            }
+            if let Ok(brightness) = self.get_led_brightness(led, persistence).await {
+                led_brightness.insert(led, brightness);
+            }
+        }
 
         DeviceInfo {
+            firmware_version: firmware_version.ok(),
+            serial: serial.ok(),
             dpi: dpi.ok(),
             dpi_range,
             dpi_stages: dpi_stages.ok(),
             polling_rate: polling_rate.ok(),
+            polling_rate_family,
             battery_level: battery_level.ok(),
             charging_status: charging_status.ok(),
+            idle_time: idle_time.ok(),
+            low_battery_threshold: low_battery_threshold.ok(),
+            leds,
+            led_effects,
+            led_brightness,
         }
     }
 
-    async fn set_batched(&self, batched: &DeviceSettings) -> anyhow::Result<()> {
-        tokio::time::sleep(RAZER_MOUSE_WAIT_TIME).await;
-
+    async fn set_batched(&self, batched: &DeviceSettings) -> Result<()> {
         if let Some(dpi) = batched.dpi {
-            self.set_dpi(dpi).await?;
+            self.set_dpi(dpi, batched.persistence).await?;
         }
         if let Some(dpi_stages) = &batched.dpi_stages {
-            self.set_dpi_stages(dpi_stages).await?;
+            self.set_dpi_stages(dpi_stages, batched.persistence).await?;
         }
         if let Some(polling_rate) = batched.polling_rate {
             self.set_polling_rate(polling_rate).await?;
         }
+        if let Some(idle_time) = batched.idle_time {
+            self.set_idle_time(idle_time).await?;
+        }
+        if let Some(low_battery_threshold) = batched.low_battery_threshold {
+            self.set_low_battery_threshold(low_battery_threshold)
+                .await?;
+        }
+        for (&led, &effect) in &batched.led_effects {
+            self.chroma_matrix_effect(led, effect, batched.persistence)
+                .await?;
+        }
+        for (&led, &brightness) in &batched.led_brightness {
+            self.set_led_brightness(led, brightness, batched.persistence)
+                .await?;
+        }
         Ok(())
     }
 }
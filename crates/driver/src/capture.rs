@@ -0,0 +1,617 @@
+use std::{collections::HashMap, time::Duration};
+
+use crate::{
+    common::{HID_REQ_GET_REPORT, HID_REQ_SET_REPORT, RAZER_REPORT_SIZE, RAZER_USB_VENDOR_ID},
+    Error, Result,
+};
+
+// https://www.tcpdump.org/linktypes.html
+const LINKTYPE_USB_LINUX: u32 = 189;
+const LINKTYPE_USB_LINUX_MMAPPED: u32 = 220;
+const LINKTYPE_USBPCAP: u32 = 249;
+
+const USB_REQ_GET_DESCRIPTOR: u8 = 0x06;
+const USB_DT_DEVICE: u16 = 0x0100;
+/// Report type feature (0x03), report id 0
+const RAZER_REPORT_VALUE: u16 = 0x300;
+
+/// Whether a report went to the device (SET_REPORT) or came back from it (GET_REPORT)
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ReportDirection {
+    SetReport,
+    GetReport,
+}
+
+/// A Razer report extracted from a USB capture
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CapturedReport {
+    /// Time since the start of the capture
+    pub timestamp: Duration,
+    pub bus: u16,
+    pub device: u16,
+    pub direction: ReportDirection,
+    pub report: Vec<u8>,
+}
+
+/// Extract the Razer reports from a pcap or pcapng capture, in capture order.
+///
+/// Supports Linux usbmon and Windows USBPcap captures. Only feature report control transfers
+/// (`wValue` 0x300) of `RAZER_REPORT_SIZE` bytes are kept, and if the capture includes a
+/// device's enumeration, devices with a vendor id other than Razer's are skipped.
+pub fn read_capture(data: &[u8]) -> Result<Vec<CapturedReport>> {
+    let packets = match data.get(0..4) {
+        Some([0x0A, 0x0D, 0x0D, 0x0A]) => read_pcapng(data)?,
+        Some(_) => read_pcap(data)?,
+        None => return Err(invalid_capture("File too short")),
+    };
+
+    let start = packets
+        .first()
+        .map_or(Duration::ZERO, |packet| packet.timestamp);
+    let mut extractor = ReportExtractor::default();
+    for packet in &packets {
+        extractor.handle(packet, packet.timestamp.saturating_sub(start));
+    }
+    Ok(extractor.finish())
+}
+
+fn invalid_capture(msg: &str) -> Error {
+    Error::InvalidArgument(format!("Invalid capture: {}", msg))
+}
+
+/// One captured frame along with the link type of the interface it was captured on
+struct Packet<'a> {
+    link_type: u32,
+    timestamp: Duration,
+    data: &'a [u8],
+}
+
+/// Reads integers from a capture file in its byte order
+#[derive(Copy, Clone)]
+struct Endian {
+    big: bool,
+}
+
+impl Endian {
+    fn u16(self, data: &[u8], offset: usize) -> Result<u16> {
+        let bytes = data
+            .get(offset..offset + 2)
+            .ok_or_else(|| invalid_capture("Truncated"))?;
+        let bytes = [bytes[0], bytes[1]];
+        Ok(if self.big {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn u32(self, data: &[u8], offset: usize) -> Result<u32> {
+        let bytes = data
+            .get(offset..offset + 4)
+            .ok_or_else(|| invalid_capture("Truncated"))?;
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        Ok(if self.big {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+}
+
+fn read_pcap(data: &[u8]) -> Result<Vec<Packet<'_>>> {
+    // Magic number tells byte order and timestamp resolution
+    let (endian, nanos) = match data.get(0..4) {
+        Some([0xD4, 0xC3, 0xB2, 0xA1]) => (Endian { big: false }, false),
+        Some([0xA1, 0xB2, 0xC3, 0xD4]) => (Endian { big: true }, false),
+        Some([0x4D, 0x3C, 0xB2, 0xA1]) => (Endian { big: false }, true),
+        Some([0xA1, 0xB2, 0x3C, 0x4D]) => (Endian { big: true }, true),
+        _ => return Err(invalid_capture("Not a pcap or pcapng file")),
+    };
+    let link_type = endian.u32(data, 20)?;
+
+    let mut packets = Vec::new();
+    let mut offset = 24;
+    while offset < data.len() {
+        let seconds = endian.u32(data, offset)? as u64;
+        let fraction = endian.u32(data, offset + 4)?;
+        let captured_len = endian.u32(data, offset + 8)? as usize;
+        let start = offset + 16;
+        let packet = data
+            .get(start..start + captured_len)
+            .ok_or_else(|| invalid_capture("Truncated packet"))?;
+
+        let fraction = if nanos {
+            Duration::from_nanos(fraction as u64)
+        } else {
+            Duration::from_micros(fraction as u64)
+        };
+        packets.push(Packet {
+            link_type,
+            timestamp: Duration::from_secs(seconds) + fraction,
+            data: packet,
+        });
+        offset = start + captured_len;
+    }
+    Ok(packets)
+}
+
+fn read_pcapng(data: &[u8]) -> Result<Vec<Packet<'_>>> {
+    const SECTION_HEADER_BLOCK: u32 = 0x0A0D0D0A;
+    const INTERFACE_DESCRIPTION_BLOCK: u32 = 0x00000001;
+    const SIMPLE_PACKET_BLOCK: u32 = 0x00000003;
+    const ENHANCED_PACKET_BLOCK: u32 = 0x00000006;
+    const IF_TSRESOL: u16 = 9;
+
+    let mut endian = Endian { big: false };
+    // (link type, timestamp units per second) of each interface in the current section
+    let mut interfaces: Vec<(u32, u64)> = Vec::new();
+    let mut packets = Vec::new();
+
+    let mut offset = 0;
+    while offset < data.len() {
+        if endian.u32(data, offset)? == SECTION_HEADER_BLOCK {
+            // Byte order magic decides how the rest of the section is read
+            endian = match data.get(offset + 8..offset + 12) {
+                Some([0x4D, 0x3C, 0x2B, 0x1A]) => Endian { big: false },
+                Some([0x1A, 0x2B, 0x3C, 0x4D]) => Endian { big: true },
+                _ => return Err(invalid_capture("Bad pcapng byte order magic")),
+            };
+            interfaces.clear();
+        }
+
+        let block_type = endian.u32(data, offset)?;
+        let block_len = endian.u32(data, offset + 4)? as usize;
+        let block = data
+            .get(offset..offset + block_len)
+            .filter(|_| block_len >= 12)
+            .ok_or_else(|| invalid_capture("Truncated block"))?;
+        let body = &block[8..block_len - 4];
+
+        match block_type {
+            INTERFACE_DESCRIPTION_BLOCK => {
+                let link_type = endian.u16(body, 0)? as u32;
+                let mut units_per_second = 1_000_000;
+
+                // Options: code, length, value padded to 4 bytes
+                let mut option = 8;
+                while option + 4 <= body.len() {
+                    let code = endian.u16(body, option)?;
+                    let len = endian.u16(body, option + 2)? as usize;
+                    if let (IF_TSRESOL, 1, Some(&resolution)) = (code, len, body.get(option + 4)) {
+                        let resolution = if resolution & 0x80 != 0 {
+                            2u64.checked_pow((resolution & 0x7F) as u32)
+                        } else {
+                            10u64.checked_pow(resolution as u32)
+                        };
+                        units_per_second = resolution
+                            .ok_or_else(|| invalid_capture("Bad timestamp resolution"))?;
+                    }
+                    if code == 0 {
+                        break;
+                    }
+                    option += 4 + len.next_multiple_of(4);
+                }
+                interfaces.push((link_type, units_per_second));
+            }
+            ENHANCED_PACKET_BLOCK => {
+                let interface = endian.u32(body, 0)? as usize;
+                let (link_type, units_per_second) = *interfaces
+                    .get(interface)
+                    .ok_or_else(|| invalid_capture("Packet for unknown interface"))?;
+                let timestamp = ((endian.u32(body, 4)? as u64) << 32) | endian.u32(body, 8)? as u64;
+                let captured_len = endian.u32(body, 12)? as usize;
+                let packet = body
+                    .get(20..20 + captured_len)
+                    .ok_or_else(|| invalid_capture("Truncated packet"))?;
+
+                packets.push(Packet {
+                    link_type,
+                    timestamp: timestamp_to_duration(timestamp, units_per_second),
+                    data: packet,
+                });
+            }
+            SIMPLE_PACKET_BLOCK => {
+                let (link_type, _) = *interfaces
+                    .first()
+                    .ok_or_else(|| invalid_capture("Packet for unknown interface"))?;
+                let original_len = endian.u32(body, 0)? as usize;
+                let packet = &body[4..(4 + original_len).min(body.len())];
+                packets.push(Packet {
+                    link_type,
+                    timestamp: Duration::ZERO,
+                    data: packet,
+                });
+            }
+            _ => {}
+        }
+        offset += block_len;
+    }
+    Ok(packets)
+}
+
+fn timestamp_to_duration(timestamp: u64, units_per_second: u64) -> Duration {
+    let seconds = timestamp / units_per_second;
+    let remainder = timestamp % units_per_second;
+    let nanos = remainder as u128 * 1_000_000_000 / units_per_second as u128;
+    Duration::new(seconds, nanos as u32)
+}
+
+/// USB setup packet of a control transfer
+#[derive(Copy, Clone)]
+struct Setup {
+    request_type: u8,
+    request: u8,
+    value: u16,
+}
+
+impl Setup {
+    fn parse(data: &[u8]) -> Option<Setup> {
+        let data = data.get(0..8)?;
+        Some(Setup {
+            request_type: data[0],
+            request: data[1],
+            value: u16::from_le_bytes([data[2], data[3]]),
+        })
+    }
+
+    fn report_direction(&self) -> Option<ReportDirection> {
+        // Class request to an interface
+        if self.request_type & 0x7F != 0x21 || self.value != RAZER_REPORT_VALUE {
+            return None;
+        }
+        let device_to_host = self.request_type & 0x80 != 0;
+        match (self.request, device_to_host) {
+            (HID_REQ_SET_REPORT, false) => Some(ReportDirection::SetReport),
+            (HID_REQ_GET_REPORT, true) => Some(ReportDirection::GetReport),
+            _ => None,
+        }
+    }
+
+    fn is_get_device_descriptor(&self) -> bool {
+        self.request_type == 0x80
+            && self.request == USB_REQ_GET_DESCRIPTOR
+            && self.value == USB_DT_DEVICE
+    }
+}
+
+/// A control transfer event, normalized from the different capture link types
+struct ControlEvent<'a> {
+    /// Identifies the transfer, to match completions with their submission
+    id: u64,
+    bus: u16,
+    device: u16,
+    /// Set on submission
+    setup: Option<Setup>,
+    /// Set on completion
+    completed: bool,
+    data: &'a [u8],
+}
+
+fn parse_usbmon(data: &[u8], header_len: usize) -> Option<ControlEvent<'_>> {
+    const XFER_CONTROL: u8 = 2;
+
+    let header = data.get(0..header_len)?;
+    if header[9] != XFER_CONTROL {
+        return None;
+    }
+    let id = u64::from_le_bytes(header[0..8].try_into().ok()?);
+    let completed = match header[8] {
+        b'S' => false,
+        b'C' => true,
+        _ => return None,
+    };
+    // A flag of 0 means the setup packet is present
+    let setup = if header[14] == 0 {
+        Setup::parse(&header[40..48])
+    } else {
+        None
+    };
+
+    Some(ControlEvent {
+        id,
+        bus: u16::from_le_bytes([header[12], header[13]]),
+        device: header[11] as u16,
+        setup,
+        completed,
+        data: &data[header_len..],
+    })
+}
+
+fn parse_usbpcap(data: &[u8]) -> Option<ControlEvent<'_>> {
+    const USBPCAP_TRANSFER_CONTROL: u8 = 2;
+    const USBPCAP_CONTROL_STAGE_SETUP: u8 = 0;
+    const USBPCAP_CONTROL_STAGE_DATA: u8 = 1;
+    const USBPCAP_CONTROL_STAGE_COMPLETE: u8 = 3;
+
+    let header_len = u16::from_le_bytes([*data.first()?, *data.get(1)?]) as usize;
+    let header = data.get(0..header_len)?;
+    if header_len < 28 || header[22] != USBPCAP_TRANSFER_CONTROL {
+        return None;
+    }
+    let id = u64::from_le_bytes(header[2..10].try_into().ok()?);
+    let from_device = header[16] & 0x01 != 0;
+    let payload = &data[header_len..];
+
+    let (setup, completed, payload) = match (header[27], from_device) {
+        // Setup packet, followed by the data of OUT transfers
+        (USBPCAP_CONTROL_STAGE_SETUP, false) => (
+            Setup::parse(payload),
+            false,
+            &payload[8.min(payload.len())..],
+        ),
+        (USBPCAP_CONTROL_STAGE_DATA, false) => (None, false, payload),
+        (USBPCAP_CONTROL_STAGE_COMPLETE | USBPCAP_CONTROL_STAGE_DATA, true) => {
+            (None, true, payload)
+        }
+        _ => return None,
+    };
+
+    Some(ControlEvent {
+        id,
+        bus: u16::from_le_bytes([header[17], header[18]]),
+        device: u16::from_le_bytes([header[19], header[20]]),
+        setup,
+        completed,
+        data: payload,
+    })
+}
+
+/// A submitted control transfer waiting for its completion
+struct PendingTransfer {
+    setup: Setup,
+    timestamp: Duration,
+    data: Vec<u8>,
+}
+
+#[derive(Default)]
+struct ReportExtractor {
+    pending: HashMap<u64, PendingTransfer>,
+    vendor_ids: HashMap<(u16, u16), u16>,
+    reports: Vec<CapturedReport>,
+}
+
+impl ReportExtractor {
+    fn handle(&mut self, packet: &Packet<'_>, timestamp: Duration) {
+        let event = match packet.link_type {
+            LINKTYPE_USB_LINUX => parse_usbmon(packet.data, 48),
+            LINKTYPE_USB_LINUX_MMAPPED => parse_usbmon(packet.data, 64),
+            LINKTYPE_USBPCAP => parse_usbpcap(packet.data),
+            _ => None,
+        };
+        let Some(event) = event else {
+            return;
+        };
+
+        if let Some(setup) = event.setup {
+            self.pending.insert(
+                event.id,
+                PendingTransfer {
+                    setup,
+                    timestamp,
+                    data: event.data.to_vec(),
+                },
+            );
+            return;
+        }
+        if !event.completed {
+            // Separate OUT data stage
+            if let Some(pending) = self.pending.get_mut(&event.id) {
+                pending.data.extend_from_slice(event.data);
+            }
+            return;
+        }
+
+        let Some(pending) = self.pending.remove(&event.id) else {
+            return;
+        };
+        let (direction, data, timestamp) = match pending.setup.report_direction() {
+            Some(ReportDirection::SetReport) => {
+                (ReportDirection::SetReport, pending.data, pending.timestamp)
+            }
+            Some(ReportDirection::GetReport) => {
+                (ReportDirection::GetReport, event.data.to_vec(), timestamp)
+            }
+            None => {
+                if pending.setup.is_get_device_descriptor() && event.data.len() >= 10 {
+                    let vendor_id = u16::from_le_bytes([event.data[8], event.data[9]]);
+                    self.vendor_ids.insert((event.bus, event.device), vendor_id);
+                }
+                return;
+            }
+        };
+        if data.len() != RAZER_REPORT_SIZE {
+            return;
+        }
+
+        self.reports.push(CapturedReport {
+            timestamp,
+            bus: event.bus,
+            device: event.device,
+            direction,
+            report: data,
+        });
+    }
+
+    fn finish(self) -> Vec<CapturedReport> {
+        let vendor_ids = self.vendor_ids;
+        let mut reports = self.reports;
+        // Devices that weren't enumerated during the capture are kept
+        reports.retain(|report| {
+            vendor_ids
+                .get(&(report.bus, report.device))
+                .is_none_or(|&vendor_id| vendor_id == RAZER_USB_VENDOR_ID)
+        });
+        reports.sort_by_key(|report| report.timestamp);
+        reports
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SET_REPORT_SETUP: [u8; 8] = [0x21, 0x09, 0x00, 0x03, 0x00, 0x00, 0x5A, 0x00];
+    const GET_REPORT_SETUP: [u8; 8] = [0xA1, 0x01, 0x00, 0x03, 0x00, 0x00, 0x5A, 0x00];
+    const GET_DEVICE_DESCRIPTOR_SETUP: [u8; 8] = [0x80, 0x06, 0x00, 0x01, 0x00, 0x00, 0x12, 0x00];
+
+    fn report(fill: u8) -> Vec<u8> {
+        vec![fill; RAZER_REPORT_SIZE]
+    }
+
+    fn device_descriptor(vendor_id: u16) -> Vec<u8> {
+        let mut descriptor = vec![0x12, 0x01, 0x00, 0x02, 0x00, 0x00, 0x00, 0x40];
+        descriptor.extend_from_slice(&vendor_id.to_le_bytes());
+        descriptor.extend_from_slice(&[0x7D, 0x00, 0x00, 0x02, 0x01, 0x02, 0x00, 0x01]);
+        descriptor
+    }
+
+    fn usbmon(id: u64, kind: u8, device: u8, setup: Option<[u8; 8]>, data: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0u8; 48];
+        packet[0..8].copy_from_slice(&id.to_le_bytes());
+        packet[8] = kind;
+        packet[9] = 2; // Control
+        packet[11] = device;
+        packet[12..14].copy_from_slice(&1u16.to_le_bytes());
+        packet[14] = if setup.is_some() { 0 } else { b'-' };
+        if let Some(setup) = setup {
+            packet[40..48].copy_from_slice(&setup);
+        }
+        packet.extend_from_slice(data);
+        packet
+    }
+
+    fn pcap(link_type: u32, packets: &[Vec<u8>]) -> Vec<u8> {
+        let mut file = vec![0xD4, 0xC3, 0xB2, 0xA1, 0x02, 0x00, 0x04, 0x00];
+        file.extend_from_slice(&[0; 8]);
+        file.extend_from_slice(&0xFFFFu32.to_le_bytes());
+        file.extend_from_slice(&link_type.to_le_bytes());
+        for (i, packet) in packets.iter().enumerate() {
+            file.extend_from_slice(&100u32.to_le_bytes());
+            file.extend_from_slice(&(i as u32 * 1000).to_le_bytes());
+            file.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+            file.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+            file.extend_from_slice(packet);
+        }
+        file
+    }
+
+    fn pcapng(link_type: u16, packets: &[Vec<u8>]) -> Vec<u8> {
+        let mut file = Vec::new();
+        let mut block = |block_type: u32, body: &[u8]| {
+            let len = 12 + body.len().next_multiple_of(4);
+            file.extend_from_slice(&block_type.to_le_bytes());
+            file.extend_from_slice(&(len as u32).to_le_bytes());
+            file.extend_from_slice(body);
+            file.resize(file.len() + body.len().next_multiple_of(4) - body.len(), 0);
+            file.extend_from_slice(&(len as u32).to_le_bytes());
+        };
+
+        block(
+            0x0A0D0D0A,
+            &[
+                0x4D, 0x3C, 0x2B, 0x1A, 0x01, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+                0xFF, 0xFF,
+            ],
+        );
+        let mut interface = link_type.to_le_bytes().to_vec();
+        interface.extend_from_slice(&[0, 0, 0xFF, 0xFF, 0, 0]);
+        block(0x00000001, &interface);
+        for (i, packet) in packets.iter().enumerate() {
+            let mut body = 0u32.to_le_bytes().to_vec();
+            body.extend_from_slice(&0u32.to_le_bytes());
+            body.extend_from_slice(&(i as u32 * 1000).to_le_bytes());
+            body.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+            body.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+            body.extend_from_slice(packet);
+            block(0x00000006, &body);
+        }
+        file
+    }
+
+    fn usbpcap(irp: u64, from_device: bool, stage: u8, data: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0u8; 28];
+        packet[0..2].copy_from_slice(&28u16.to_le_bytes());
+        packet[2..10].copy_from_slice(&irp.to_le_bytes());
+        packet[16] = from_device as u8;
+        packet[17..19].copy_from_slice(&1u16.to_le_bytes());
+        packet[19..21].copy_from_slice(&3u16.to_le_bytes());
+        packet[22] = 2; // Control
+        packet[23..27].copy_from_slice(&(data.len() as u32).to_le_bytes());
+        packet[27] = stage;
+        packet.extend_from_slice(data);
+        packet
+    }
+
+    #[test]
+    fn usbmon_pcap() {
+        let packets = [
+            usbmon(1, b'S', 5, Some(GET_DEVICE_DESCRIPTOR_SETUP), &[]),
+            usbmon(1, b'C', 5, None, &device_descriptor(RAZER_USB_VENDOR_ID)),
+            usbmon(2, b'S', 5, Some(SET_REPORT_SETUP), &report(0xAA)),
+            usbmon(2, b'C', 5, None, &[]),
+            usbmon(3, b'S', 5, Some(GET_REPORT_SETUP), &[]),
+            usbmon(3, b'C', 5, None, &report(0xBB)),
+            // Another vendor's device using the same report value
+            usbmon(4, b'S', 6, Some(GET_DEVICE_DESCRIPTOR_SETUP), &[]),
+            usbmon(4, b'C', 6, None, &device_descriptor(0x046D)),
+            usbmon(5, b'S', 6, Some(SET_REPORT_SETUP), &report(0xCC)),
+            usbmon(5, b'C', 6, None, &[]),
+        ];
+
+        let reports = read_capture(&pcap(LINKTYPE_USB_LINUX, &packets)).unwrap();
+        assert_eq!(
+            reports,
+            vec![
+                CapturedReport {
+                    timestamp: Duration::from_millis(2),
+                    bus: 1,
+                    device: 5,
+                    direction: ReportDirection::SetReport,
+                    report: report(0xAA),
+                },
+                CapturedReport {
+                    timestamp: Duration::from_millis(5),
+                    bus: 1,
+                    device: 5,
+                    direction: ReportDirection::GetReport,
+                    report: report(0xBB),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn usbpcap_pcapng() {
+        let packets = [
+            usbpcap(
+                7,
+                false,
+                0,
+                &[SET_REPORT_SETUP.as_slice(), &report(0xAA)].concat(),
+            ),
+            usbpcap(7, true, 3, &[]),
+            usbpcap(8, false, 0, &GET_REPORT_SETUP),
+            usbpcap(8, true, 3, &report(0xBB)),
+        ];
+
+        let reports = read_capture(&pcapng(LINKTYPE_USBPCAP as u16, &packets)).unwrap();
+        let summary: Vec<_> = reports
+            .iter()
+            .map(|report| (report.device, report.direction, report.report[0]))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (3, ReportDirection::SetReport, 0xAA),
+                (3, ReportDirection::GetReport, 0xBB),
+            ]
+        );
+        assert_eq!(reports[1].timestamp, Duration::from_millis(3));
+    }
+
+    #[test]
+    fn invalid_capture_files() {
+        assert!(read_capture(&[]).is_err());
+        assert!(read_capture(b"not a capture").is_err());
+    }
+}
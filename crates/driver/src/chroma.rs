@@ -1,35 +1,172 @@
-use std::str::FromStr;
+use std::{fmt, str::FromStr};
 
-#[derive(Clone, Debug)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
 #[repr(u8)]
 pub enum LedId {
     // Zero = 0x00,
-    // ScrollWeel = 0x01,
-    // Battery = 0x03,
+    ScrollWheel = 0x01,
+    Battery = 0x03,
     Logo = 0x04,
-    // Backlight = 0x05,
-    // Macro = 0x07,
-    // Game = 0x08,
+    Backlight = 0x05,
+    Macro = 0x07,
+    Game = 0x08,
+    // Side panels, e.g. of a Naga
+    RightSide = 0x10,
+    LeftSide = 0x11,
 }
 
-#[derive(Copy, Clone)]
+impl TryFrom<u8> for LedId {
+    type Error = ();
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x01 => Ok(LedId::ScrollWheel),
+            0x03 => Ok(LedId::Battery),
+            0x04 => Ok(LedId::Logo),
+            0x05 => Ok(LedId::Backlight),
+            0x07 => Ok(LedId::Macro),
+            0x08 => Ok(LedId::Game),
+            0x10 => Ok(LedId::RightSide),
+            0x11 => Ok(LedId::LeftSide),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for LedId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LedId::ScrollWheel => "Scroll Wheel",
+            LedId::Battery => "Battery",
+            LedId::Logo => "Logo",
+            LedId::Backlight => "Backlight",
+            LedId::Macro => "Macro",
+            LedId::Game => "Game",
+            LedId::RightSide => "Right Side",
+            LedId::LeftSide => "Left Side",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum BreathingEffect {
     Single(Color),
     Dual(Color, Color),
     Random,
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum StarlightEffect {
+    Single(Color),
+    Dual(Color, Color),
+    Random,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[repr(u8)]
+pub enum WaveDirection {
+    Left = 0x01,
+    Right = 0x02,
+}
+
+impl TryFrom<u8> for WaveDirection {
+    type Error = ();
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x01 => Ok(WaveDirection::Left),
+            0x02 => Ok(WaveDirection::Right),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for WaveDirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WaveDirection::Left => write!(f, "left"),
+            WaveDirection::Right => write!(f, "right"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[repr(u8)]
+pub enum WheelDirection {
+    Clockwise = 0x01,
+    CounterClockwise = 0x02,
+}
+
+impl TryFrom<u8> for WheelDirection {
+    type Error = ();
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x01 => Ok(WheelDirection::Clockwise),
+            0x02 => Ok(WheelDirection::CounterClockwise),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for WheelDirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WheelDirection::Clockwise => write!(f, "clockwise"),
+            WheelDirection::CounterClockwise => write!(f, "counter-clockwise"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum ExtendedMatrixEffect {
     None,
     Static(Color),
     Breathing(BreathingEffect),
     Spectrum,
-    // Wave = 0x04,
+    /// Wave effect, direction with speed from 0x01 to 0xFF (lower is faster, Synapse uses 0x28)
+    Wave(WaveDirection, u8),
     /// Reactive effect, color with speed
     Reactive(Color, u8),
-    // Starlight = 0x07,
-    // Wheel = 0x0A,
+    /// Starlight effect, colors with speed
+    Starlight(StarlightEffect, u8),
+    Wheel(WheelDirection),
+}
+
+impl fmt::Display for ExtendedMatrixEffect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExtendedMatrixEffect::None => write!(f, "Off"),
+            ExtendedMatrixEffect::Static(color) => write!(f, "Static {}", color),
+            ExtendedMatrixEffect::Breathing(BreathingEffect::Single(color)) => {
+                write!(f, "Breathing {}", color)
+            }
+            ExtendedMatrixEffect::Breathing(BreathingEffect::Dual(color1, color2)) => {
+                write!(f, "Breathing {} {}", color1, color2)
+            }
+            ExtendedMatrixEffect::Breathing(BreathingEffect::Random) => {
+                write!(f, "Breathing random colors")
+            }
+            ExtendedMatrixEffect::Spectrum => write!(f, "Spectrum"),
+            ExtendedMatrixEffect::Wave(direction, speed) => {
+                write!(f, "Wave {}, speed {}", direction, speed)
+            }
+            ExtendedMatrixEffect::Reactive(color, speed) => {
+                write!(f, "Reactive {}, speed {}", color, speed)
+            }
+            ExtendedMatrixEffect::Starlight(StarlightEffect::Single(color), speed) => {
+                write!(f, "Starlight {}, speed {}", color, speed)
+            }
+            ExtendedMatrixEffect::Starlight(StarlightEffect::Dual(color1, color2), speed) => {
+                write!(f, "Starlight {} {}, speed {}", color1, color2, speed)
+            }
+            ExtendedMatrixEffect::Starlight(StarlightEffect::Random, speed) => {
+                write!(f, "Starlight random colors, speed {}", speed)
+            }
+            ExtendedMatrixEffect::Wheel(direction) => write!(f, "Wheel {}", direction),
+        }
+    }
 }
 
 impl From<ExtendedMatrixEffect> for u8 {
@@ -39,18 +176,28 @@ impl From<ExtendedMatrixEffect> for u8 {
             ExtendedMatrixEffect::Static(..) => 0x01,
             ExtendedMatrixEffect::Breathing(..) => 0x02,
             ExtendedMatrixEffect::Spectrum => 0x03,
+            ExtendedMatrixEffect::Wave(..) => 0x04,
             ExtendedMatrixEffect::Reactive(..) => 0x05,
+            ExtendedMatrixEffect::Starlight(..) => 0x07,
+            ExtendedMatrixEffect::Wheel(..) => 0x0A,
         }
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub struct Color {
     pub r: u8,
     pub g: u8,
     pub b: u8,
 }
 
+impl fmt::Display for Color {
+    /// The hex code `FromStr` parses
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
+
 impl FromStr for Color {
     type Err = ();
 
@@ -1,8 +1,7 @@
-use crate::chroma::{BreathingEffect, ExtendedMatrixEffect, LedId};
-use anyhow::{anyhow, Error, Result};
-use nusb::{
-    transfer::{ControlIn, ControlOut, ControlType, Recipient},
-    Interface,
+use crate::{
+    protocol::{Command, Response},
+    transport::RazerTransport,
+    Error, Result,
 };
 use std::{
     cmp::{max, min},
@@ -11,20 +10,63 @@ use std::{
 use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout};
 
 pub const RAZER_USB_VENDOR_ID: u16 = 0x1532;
-pub(crate) const RAZER_REPORT_SIZE: usize = size_of::<RazerMessage>();
+pub const RAZER_REPORT_SIZE: usize = size_of::<RazerMessage>();
 pub(crate) const RAZER_REPORT_ARGUMENT_SIZE: usize = 80;
 pub(crate) const RAZER_USB_INTERFACE_NUMBER: u8 = 0x00;
-pub(crate) const RAZER_MOUSE_WAIT_TIME: Duration = Duration::from_millis(60);
+/// How long to wait before first reading a response, doubled every time the device is still busy
+pub(crate) const RAZER_RESPONSE_POLL_INTERVAL: Duration = Duration::from_millis(2);
+pub(crate) const RAZER_RESPONSE_MAX_POLL_INTERVAL: Duration = Duration::from_millis(32);
+pub(crate) const RAZER_RESPONSE_MAX_POLLS: u32 = 8;
+/// Minimum time between the end of one command and the start of the next on a device
+pub(crate) const RAZER_COMMAND_GAP: Duration = Duration::from_millis(10);
+/// How long a command may take, from sending the request to reading the final response
+pub const RAZER_DEFAULT_COMMAND_TIMEOUT: Duration = Duration::from_secs(1);
 pub const RAZER_MOUSE_MAX_DPI_STAGES: u8 = 5;
 
 pub(crate) const RAZER_MOUSE_MIN_DPI: u16 = 100;
 pub(crate) const RAZER_MOUSE_MAX_DPI: u16 = 35000;
 
+/// How long a wireless mouse may sit idle before it goes to sleep
+pub const RAZER_MIN_IDLE_TIME: Duration = Duration::from_secs(60);
+pub const RAZER_MAX_IDLE_TIME: Duration = Duration::from_secs(900);
+/// Battery percentage below which a wireless mouse starts saving power, e.g. by dimming its LEDs
+pub const RAZER_MIN_LOW_BATTERY_THRESHOLD: u8 = 5;
+pub const RAZER_MAX_LOW_BATTERY_THRESHOLD: u8 = 25;
+
 // linux/hid.h
 pub(crate) const HID_REQ_GET_REPORT: u8 = 0x01;
 pub(crate) const HID_REQ_SET_REPORT: u8 = 0x09;
 
-#[derive(Immutable, KnownLayout, IntoBytes, FromBytes, Debug)]
+/// Status byte at the start of every report. Requests are sent as `New`, the device answers
+/// with one of the others.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[repr(u8)]
+pub(crate) enum RazerStatus {
+    New = 0x00,
+    Busy = 0x01,
+    Success = 0x02,
+    Failure = 0x03,
+    Timeout = 0x04,
+    NotSupported = 0x05,
+}
+
+impl TryFrom<u8> for RazerStatus {
+    type Error = u8;
+
+    fn try_from(value: u8) -> std::result::Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(RazerStatus::New),
+            0x01 => Ok(RazerStatus::Busy),
+            0x02 => Ok(RazerStatus::Success),
+            0x03 => Ok(RazerStatus::Failure),
+            0x04 => Ok(RazerStatus::Timeout),
+            0x05 => Ok(RazerStatus::NotSupported),
+            _ => Err(value),
+        }
+    }
+}
+
+#[derive(Immutable, KnownLayout, IntoBytes, FromBytes, Clone, Debug)]
 #[repr(C)]
 pub(crate) struct RazerMessage {
     status: u8,
@@ -40,9 +82,82 @@ pub(crate) struct RazerMessage {
 }
 
 impl RazerMessage {
+    pub(crate) fn status(&self) -> u8 {
+        self.status
+    }
+
+    pub(crate) fn set_status(&mut self, status: RazerStatus) {
+        self.status = status as u8;
+    }
+
+    pub(crate) fn transaction_id(&self) -> u8 {
+        self.transaction_id
+    }
+
+    pub(crate) fn data_size(&self) -> u8 {
+        self.data_size
+    }
+
+    pub(crate) fn command_class(&self) -> u8 {
+        self.command_class
+    }
+
+    pub(crate) fn command_id(&self) -> u8 {
+        self.command_id
+    }
+
     pub(crate) fn arguments(&self) -> &[u8; 80] {
         &self.arguments
     }
+
+    pub(crate) fn arguments_mut(&mut self) -> &mut [u8; 80] {
+        &mut self.arguments
+    }
+
+    pub(crate) fn crc(&self) -> u8 {
+        self.crc
+    }
+
+    /// The CRC this message should have
+    pub(crate) fn expected_crc(&self) -> u8 {
+        RazerMessageBuilder::calculate_crc(self)
+    }
+
+    /// Recalculate the CRC after modifying the message in place
+    pub(crate) fn update_crc(&mut self) {
+        self.crc = RazerMessageBuilder::calculate_crc(self);
+    }
+
+    /// Check that `self` is an intact response to `request`, and not e.g. a stale response
+    /// to a previous command that's still sitting in the device.
+    pub(crate) fn validate_response(&self, request: &RazerMessage) -> Result<()> {
+        let crc = RazerMessageBuilder::calculate_crc(self);
+        if self.crc != crc {
+            return Err(Error::InvalidResponse(format!(
+                "Bad CRC: got {:#04x}, expected {:#04x}",
+                self.crc, crc
+            )));
+        }
+        if self.transaction_id != request.transaction_id
+            || self.command_class != request.command_class
+            || self.command_id != request.command_id
+            || self.data_size != request.data_size
+        {
+            return Err(Error::InvalidResponse(format!(
+                "Response (transaction {:#04x}, command {:#04x}:{:#04x}, size {:#04x}) \
+                 doesn't match request (transaction {:#04x}, command {:#04x}:{:#04x}, size {:#04x})",
+                self.transaction_id,
+                self.command_class,
+                self.command_id,
+                self.data_size,
+                request.transaction_id,
+                request.command_class,
+                request.command_id,
+                request.data_size,
+            )));
+        }
+        Ok(())
+    }
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -66,6 +181,37 @@ impl From<(u16, u16)> for Dpi {
     }
 }
 
+/// `1600` when X and Y are the same, `1600x800` otherwise
+impl std::fmt::Display for Dpi {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.x == self.y {
+            write!(f, "{}", self.x)
+        } else {
+            write!(f, "{}x{}", self.x, self.y)
+        }
+    }
+}
+
+/// Parses `1600` as 1600 DPI on both axes and `1600x800` as X and Y, like `Dpi` displays
+impl std::str::FromStr for Dpi {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parse_axis = |axis: &str| {
+            axis.trim().parse::<u16>().map_err(|_| {
+                Error::InvalidArgument(format!("Invalid DPI \"{}\", expected X or XxY", s))
+            })
+        };
+        match s.split_once(['x', 'X']) {
+            Some((x, y)) => Ok(Dpi {
+                x: parse_axis(x)?,
+                y: parse_axis(y)?,
+            }),
+            None => Ok(parse_axis(s)?.into()),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct DpiStages {
     active: u8,
@@ -76,12 +222,14 @@ impl DpiStages {
     /// `active` should be a 0-based index
     pub fn new(active: u8, stages: Vec<Dpi>) -> Result<DpiStages> {
         if stages.is_empty() || stages.len() > RAZER_MOUSE_MAX_DPI_STAGES as usize {
-            Err(anyhow!(
+            Err(Error::InvalidArgument(format!(
                 "DpiStages: Need 1 <= # of DPI stages <= {}",
                 RAZER_MOUSE_MAX_DPI_STAGES
+            )))
+        } else if active as usize >= stages.len() {
+            Err(Error::InvalidArgument(
+                "DpiStages: Need 1 <= active stage <= # of stages".into(),
             ))
-        } else if active > stages.len() as u8 {
-            Err(anyhow!("DpiStages: Need 1 <= active stage <= # of stages"))
         } else {
             Ok(DpiStages { active, stages })
         }
@@ -91,7 +239,12 @@ impl DpiStages {
         self.active
     }
 
-    fn active_1_idx(&self) -> u8 {
+    /// The same stages with another one active, `active` being a 0-based index
+    pub fn with_active(&self, active: u8) -> Result<DpiStages> {
+        DpiStages::new(active, self.stages.clone())
+    }
+
+    pub(crate) fn active_1_idx(&self) -> u8 {
         self.active + 1
     }
 
@@ -102,17 +255,17 @@ impl DpiStages {
 
 #[derive(Debug)]
 pub(crate) struct RazerMessageBuilder {
-    transaction_id: u8,
-    data_size: u8,
-    command_class: u8,
-    command_id: u8,
-    arguments: [u8; RAZER_REPORT_ARGUMENT_SIZE],
+    pub(crate) transaction_id: u8,
+    pub(crate) data_size: u8,
+    pub(crate) command_class: u8,
+    pub(crate) command_id: u8,
+    pub(crate) arguments: [u8; RAZER_REPORT_ARGUMENT_SIZE],
 }
 
 impl RazerMessageBuilder {
     pub(crate) fn build(self) -> RazerMessage {
         let mut result = RazerMessage {
-            status: 0x00,
+            status: RazerStatus::New as u8,
             transaction_id: self.transaction_id,
             remaining_packets: 0x0000,
             protocol_type: 0x00,
@@ -132,197 +285,6 @@ impl RazerMessageBuilder {
         self
     }
 
-    /// Message to send to the device asking for battery level.
-    pub(crate) fn get_battery_level() -> Self {
-        Self {
-            data_size: 0x02,
-            command_class: 0x07,
-            command_id: 0x80,
-            ..Default::default()
-        }
-    }
-
-    pub(crate) fn get_charging_status() -> Self {
-        Self {
-            data_size: 0x02,
-            command_class: 0x07,
-            command_id: 0x84,
-            ..Default::default()
-        }
-    }
-
-    pub(crate) fn get_dpi(var_store: VarStoreId) -> Self {
-        let mut msg = Self {
-            data_size: 0x07,
-            command_class: 0x04,
-            command_id: 0x85,
-            ..Default::default()
-        };
-        msg.arguments[0] = var_store as u8;
-        msg
-    }
-
-    pub(crate) fn set_dpi(var_store: VarStoreId, dpi: Dpi) -> Self {
-        let mut msg = Self {
-            data_size: 0x07,
-            command_class: 0x04,
-            command_id: 0x05,
-            ..Default::default()
-        };
-        let dpi_x = clamp(dpi.x, RAZER_MOUSE_MIN_DPI, RAZER_MOUSE_MAX_DPI);
-        let dpi_y = clamp(dpi.y, RAZER_MOUSE_MIN_DPI, RAZER_MOUSE_MAX_DPI);
-
-        msg.arguments[0] = var_store as u8;
-        msg.arguments[1] = ((dpi_x >> 8) & 0x00FF) as u8;
-        msg.arguments[2] = (dpi_x & 0xFF) as u8;
-        msg.arguments[3] = ((dpi_y >> 8) & 0x00FF) as u8;
-        msg.arguments[4] = (dpi_y & 0xFF) as u8;
-        msg.arguments[5] = 0x00;
-        msg.arguments[6] = 0x00;
-        msg
-    }
-
-    pub(crate) fn get_dpi_stages(var_store: VarStoreId) -> Self {
-        let mut msg = Self {
-            data_size: 0x26,
-            command_class: 0x04,
-            command_id: 0x86,
-            ..Default::default()
-        };
-        msg.arguments[0] = var_store as u8;
-        msg
-    }
-
-    pub(crate) fn set_dpi_stages(var_store: VarStoreId, dpi_stages: &DpiStages) -> Self {
-        let mut msg = Self {
-            data_size: 0x26,
-            command_class: 0x04,
-            command_id: 0x06,
-            ..Default::default()
-        };
-
-        msg.arguments[0] = var_store as u8;
-        msg.arguments[1] = dpi_stages.active_1_idx();
-
-        let num_stages = dpi_stages.stages.len();
-        msg.arguments[2] = num_stages as u8;
-
-        // We write for each stage
-        // nn       - stage number
-        // xx xx    - DPI X (u16)
-        // yy yy    - DPI Y (u16)
-        // 00 00    - Reserved
-        msg.arguments[3..]
-            .chunks_exact_mut(0x07)
-            .take(num_stages)
-            .enumerate()
-            .for_each(|(i, chunk)| {
-                let Dpi { x, y } = dpi_stages.stages[i];
-                chunk[0] = i as u8;
-                chunk[1..=2].copy_from_slice(&encode_u16_as_bytes(x));
-                chunk[3..=4].copy_from_slice(&encode_u16_as_bytes(y));
-                chunk[5] = 0;
-                chunk[6] = 0;
-            });
-        msg
-    }
-
-    pub(crate) fn get_polling_rate() -> Self {
-        Self {
-            data_size: 0x01,
-            command_class: 0x00,
-            command_id: 0x85,
-            ..Default::default()
-        }
-    }
-
-    pub(crate) fn set_polling_rate(polling_rate: NormalPollingRate) -> Self {
-        let mut msg = Self {
-            data_size: 0x01,
-            command_class: 0x00,
-            command_id: 0x05,
-            ..Default::default()
-        };
-        msg.arguments[0] = match polling_rate {
-            NormalPollingRate::Rate1000 => 0x01,
-            NormalPollingRate::Rate500 => 0x02,
-            NormalPollingRate::Rate125 => 0x08,
-        };
-        msg
-    }
-
-    #[allow(unused)]
-    pub(crate) fn set_polling_rate_extended(polling_rate: ExtendedPollingRate) -> Self {
-        let mut msg = Self {
-            data_size: 0x02,
-            command_class: 0x00,
-            command_id: 0x40,
-            ..Default::default()
-        };
-        msg.arguments[0] = 0x00; // https://github.com/openrazer/openrazer/blob/16d13ef025d7cd32f03a6acc0548f9316e42b35a/driver/razermouse_driver.c#L1681
-        msg.arguments[1] = match polling_rate {
-            ExtendedPollingRate::Rate8000 => 0x01,
-            ExtendedPollingRate::Rate4000 => 0x02,
-            ExtendedPollingRate::Rate2000 => 0x04,
-            ExtendedPollingRate::Rate1000 => 0x08,
-            ExtendedPollingRate::Rate500 => 0x10,
-            ExtendedPollingRate::Rate250 => 0x20,
-            ExtendedPollingRate::Rate125 => 0x40,
-        };
-        msg
-    }
-
-    pub(crate) fn chroma_extended_matrix_effect(
-        var_store: VarStoreId,
-        led_id: LedId,
-        effect: ExtendedMatrixEffect,
-    ) -> Self {
-        let mut msg = Self {
-            command_class: 0x0F,
-            command_id: 0x02,
-            ..Default::default()
-        };
-        msg.arguments[0] = var_store as u8;
-        msg.arguments[1] = led_id as u8;
-        msg.arguments[2] = effect.into();
-
-        match effect {
-            ExtendedMatrixEffect::None | ExtendedMatrixEffect::Spectrum => {
-                msg.data_size = 0x06;
-            }
-            ExtendedMatrixEffect::Static(color) => {
-                let payload = [0x01, color.r, color.g, color.b];
-                msg.arguments[5..=8].copy_from_slice(&payload);
-                msg.data_size = 0x09;
-            }
-            ExtendedMatrixEffect::Breathing(effect) => match effect {
-                BreathingEffect::Single(color) => {
-                    let payload = [0x01, 0x00, 0x01, color.r, color.g, color.b];
-                    msg.arguments[3..=8].copy_from_slice(&payload);
-                    msg.data_size = 0x09;
-                }
-                BreathingEffect::Dual(color, color1) => {
-                    let payload = [
-                        0x02, 0x00, 0x02, color.r, color.g, color.b, color1.r, color1.g, color1.b,
-                    ];
-                    msg.arguments[3..=11].copy_from_slice(&payload);
-                    msg.data_size = 0x0C;
-                }
-                BreathingEffect::Random => {
-                    msg.data_size = 0x06;
-                }
-            },
-            ExtendedMatrixEffect::Reactive(color, speed) => {
-                let speed = clamp(speed, 0x01, 0x04);
-
-                let payload = [speed, 0x01, color.r, color.g, color.b];
-                msg.arguments[4..=8].copy_from_slice(&payload);
-                msg.data_size = 0x09;
-            }
-        }
-        msg
-    }
-
     fn calculate_crc(report: &RazerMessage) -> u8 {
         let report = report.as_bytes();
         let mut crc: u8 = 0;
@@ -379,6 +341,49 @@ impl std::fmt::Display for PollingRate {
     }
 }
 
+/// Which polling rates a device supports, and so which commands read and write them
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum PollingRateFamily {
+    /// Up to 1000 Hz
+    Normal,
+    /// Up to 8000 Hz, with a HyperPolling receiver or on 8K mice
+    Extended,
+}
+
+impl PollingRateFamily {
+    /// Every supported rate, slowest first
+    pub fn rates(self) -> Vec<PollingRate> {
+        match self {
+            PollingRateFamily::Normal => [
+                NormalPollingRate::Rate125,
+                NormalPollingRate::Rate500,
+                NormalPollingRate::Rate1000,
+            ]
+            .map(PollingRate::from)
+            .to_vec(),
+            PollingRateFamily::Extended => [
+                ExtendedPollingRate::Rate125,
+                ExtendedPollingRate::Rate250,
+                ExtendedPollingRate::Rate500,
+                ExtendedPollingRate::Rate1000,
+                ExtendedPollingRate::Rate2000,
+                ExtendedPollingRate::Rate4000,
+                ExtendedPollingRate::Rate8000,
+            ]
+            .map(PollingRate::from)
+            .to_vec(),
+        }
+    }
+
+    /// The rate of `hz` in this family, if it's supported
+    pub fn rate(self, hz: u16) -> Option<PollingRate> {
+        match self {
+            PollingRateFamily::Normal => NormalPollingRate::try_from(hz).ok().map(Into::into),
+            PollingRateFamily::Extended => ExtendedPollingRate::try_from(hz).ok().map(Into::into),
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum NormalPollingRate {
     Rate1000,
@@ -451,54 +456,120 @@ impl TryFrom<u16> for ExtendedPollingRate {
     }
 }
 
+/// Whether the device handles its features itself, or leaves them to host software
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum DeviceMode {
+    Normal,
+    /// Host software drives features like lighting
+    Driver,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct FirmwareVersion {
+    pub major: u8,
+    pub minor: u8,
+}
+
+impl std::fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "v{}.{}", self.major, self.minor)
+    }
+}
+
+/// Where a setting is written to, or read from
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum Persistence {
+    /// Only what the mouse currently uses, lost when it's power cycled
+    #[default]
+    Temporary,
+    /// Saved in the mouse's onboard memory, kept across power cycles and computers
+    OnboardMemory,
+}
+
+impl From<Persistence> for VarStoreId {
+    fn from(value: Persistence) -> Self {
+        match value {
+            Persistence::Temporary => VarStoreId::NoStore,
+            Persistence::OnboardMemory => VarStoreId::VarStore,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 #[repr(u8)]
 pub(crate) enum VarStoreId {
     NoStore = 0x00,
     VarStore = 0x01,
 }
 
-pub(crate) async fn send_razer_message(interface: Interface, request: RazerMessage) -> Result<()> {
-    let control_message = usb_out_message(request.as_bytes());
-    interface.control_out(control_message).await.into_result()?;
-    Ok(())
+impl TryFrom<u8> for VarStoreId {
+    type Error = ();
+
+    fn try_from(value: u8) -> std::result::Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(VarStoreId::NoStore),
+            0x01 => Ok(VarStoreId::VarStore),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Send `command` and decode the device's response to it.
+pub(crate) async fn send_command(
+    transport: &dyn RazerTransport,
+    transaction_id: u8,
+    command: Command,
+) -> Result<Response> {
+    let request = command.encode().with_transaction_id(transaction_id).build();
+    let response = send_razer_message_and_wait_response(transport, request).await?;
+    Response::decode(&response)
 }
 
+/// Send a request and poll the response until the device reports a final status, backing off
+/// while it's busy. Stale or corrupted responses are polled again, as the right one may
+/// still show up.
 pub(crate) async fn send_razer_message_and_wait_response(
-    interface: Interface,
+    transport: &dyn RazerTransport,
     request: RazerMessage,
 ) -> Result<RazerMessage> {
-    send_razer_message(interface.clone(), request).await?;
-    // Need to wait for some time before asking to avoid garbage response data
-    tokio::time::sleep(RAZER_MOUSE_WAIT_TIME).await;
-
-    // Get response
-    let control_message = usb_in_message();
-    let data = interface.control_in(control_message).await.into_result()?;
-    let response = RazerMessage::read_from_bytes(&data)
-        .map_err(|_| Error::msg("Invalid size of byte response"))?;
-    Ok(response)
-}
-
-fn usb_out_message(data: &[u8]) -> ControlOut {
-    ControlOut {
-        control_type: ControlType::Class,
-        recipient: Recipient::Interface,
-        request: HID_REQ_SET_REPORT,
-        value: 0x300,
-        index: 0x00,
-        data,
-    }
-}
+    transport.send_report(request.as_bytes()).await?;
+
+    let mut wait_time = RAZER_RESPONSE_POLL_INTERVAL;
+    let mut last_error = Error::Busy;
+    for _ in 0..RAZER_RESPONSE_MAX_POLLS {
+        tokio::time::sleep(wait_time).await;
+        wait_time = min(wait_time * 2, RAZER_RESPONSE_MAX_POLL_INTERVAL);
+
+        let data = transport.receive_report().await?;
+        let response = RazerMessage::read_from_bytes(&data)
+            .map_err(|_| Error::InvalidResponse("Invalid size of byte response".into()))?;
+        if let Err(err) = response.validate_response(&request) {
+            last_error = err;
+            continue;
+        }
 
-fn usb_in_message() -> ControlIn {
-    ControlIn {
-        control_type: ControlType::Class,
-        recipient: Recipient::Interface,
-        request: HID_REQ_GET_REPORT,
-        value: 0x300,
-        index: 0x00,
-        length: RAZER_REPORT_SIZE as u16,
+        match RazerStatus::try_from(response.status) {
+            Ok(RazerStatus::Success) => return Ok(response),
+            // Not processed yet
+            Ok(RazerStatus::New | RazerStatus::Busy) => {
+                last_error = Error::Busy;
+            }
+            Ok(RazerStatus::Failure) => {
+                return Err(Error::DeviceRejected {
+                    status: response.status,
+                })
+            }
+            Ok(RazerStatus::Timeout) => return Err(Error::Timeout),
+            Ok(RazerStatus::NotSupported) => return Err(Error::Unsupported),
+            Err(status) => {
+                return Err(Error::InvalidResponse(format!(
+                    "Invalid response status: {:#04x}",
+                    status
+                )))
+            }
+        }
     }
+    Err(last_error)
 }
 
 pub(crate) fn clamp<T: Ord>(val: T, min_range: T, max_range: T) -> T {
@@ -514,3 +585,35 @@ pub(crate) fn decode_u16_from_bytes(val: &[u8]) -> u16 {
 pub(crate) fn encode_u16_as_bytes(val: u16) -> [u8; 2] {
     [((val >> 8) & 0xFF) as u8, (val & 0xFF) as u8]
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dpi_parses_what_it_displays() {
+        for dpi in [Dpi::from(1600), Dpi::from((1600, 800))] {
+            assert_eq!(dpi.to_string().parse::<Dpi>().unwrap(), dpi);
+        }
+        assert_eq!("800X400".parse::<Dpi>().unwrap(), Dpi { x: 800, y: 400 });
+        assert!("1600x".parse::<Dpi>().is_err());
+        assert!("x800".parse::<Dpi>().is_err());
+        assert!("1600x800x400".parse::<Dpi>().is_err());
+    }
+
+    #[test]
+    fn dpi_stages_need_a_valid_active_stage() {
+        let stages = vec![400.into(), 800.into()];
+        assert!(DpiStages::new(1, stages.clone()).is_ok());
+        assert!(DpiStages::new(2, stages.clone()).is_err());
+        assert!(DpiStages::new(0, Vec::new()).is_err());
+        assert_eq!(
+            DpiStages::new(0, stages)
+                .unwrap()
+                .with_active(1)
+                .unwrap()
+                .active(),
+            1
+        );
+    }
+}
@@ -1,77 +1,291 @@
-use std::ops::Deref;
+use std::{fs::OpenOptions, ops::Deref, path::PathBuf, time::Duration};
 
-use anyhow::{anyhow, Result};
 use async_trait::async_trait;
 use driver_macros::device_impls;
-use nusb::{DeviceInfo, Interface};
+use nusb::DeviceInfo;
+use tokio::sync::broadcast;
 
 use crate::{
     chroma::{ExtendedMatrixEffect, LedId},
     common::{
-        decode_u16_from_bytes, send_razer_message, send_razer_message_and_wait_response, Dpi,
-        DpiStages, NormalPollingRate, PollingRate, RazerMessageBuilder, VarStoreId,
+        DeviceMode, Dpi, DpiStages, FirmwareVersion, Persistence, PollingRate, PollingRateFamily,
+        VarStoreId, RAZER_DEFAULT_COMMAND_TIMEOUT, RAZER_MAX_IDLE_TIME,
+        RAZER_MAX_LOW_BATTERY_THRESHOLD, RAZER_MIN_IDLE_TIME, RAZER_MIN_LOW_BATTERY_THRESHOLD,
         RAZER_MOUSE_MAX_DPI, RAZER_MOUSE_MIN_DPI, RAZER_USB_INTERFACE_NUMBER,
     },
+    protocol::{Command, Response},
+    queue::{CommandQueue, Reconnect},
+    recording::RecordingTransport,
+    simulator::SimulatedMouse,
+    transport::{NusbTransport, RazerTransport},
+    Error, Result,
 };
 
-// struct SupportedLedFeatures {
-//     led: LedId,
-//     features: Vec<ExtendedMatrixEffect>,
-// }
-
-// struct SupportedLeds(Vec<SupportedLedFeatures>);
-
 #[async_trait]
 pub trait FeatureSet: Send + Sync {
-    async fn get_dpi(&self) -> Result<Dpi> {
-        Err(anyhow!("Unimplemented"))
+    async fn get_firmware_version(&self) -> Result<FirmwareVersion> {
+        Err(Error::Unsupported)
+    }
+    async fn get_serial(&self) -> Result<String> {
+        Err(Error::Unsupported)
+    }
+    async fn get_device_mode(&self) -> Result<DeviceMode> {
+        Err(Error::Unsupported)
     }
-    async fn set_dpi(&self, _: Dpi) -> Result<()> {
-        Err(anyhow!("Unimplemented"))
+    async fn set_device_mode(&self, _: DeviceMode) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+    async fn get_dpi(&self, _: Persistence) -> Result<Dpi> {
+        Err(Error::Unsupported)
+    }
+    async fn set_dpi(&self, _: Dpi, _: Persistence) -> Result<()> {
+        Err(Error::Unsupported)
     }
     fn get_dpi_range(&self) -> (u16, u16) {
         (RAZER_MOUSE_MIN_DPI, RAZER_MOUSE_MAX_DPI)
     }
-    async fn get_dpi_stages(&self) -> Result<DpiStages> {
-        Err(anyhow!("Unimplemented"))
+    async fn get_dpi_stages(&self, _: Persistence) -> Result<DpiStages> {
+        Err(Error::Unsupported)
+    }
+    async fn set_dpi_stages(&self, _: &DpiStages, _: Persistence) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+    /// The polling rates the device supports, `None` if it can't change its polling rate
+    fn get_polling_rate_family(&self) -> Option<PollingRateFamily> {
+        None
     }
-    async fn set_dpi_stages(&self, _: &DpiStages) -> Result<()> {
-        Err(anyhow!("Unimplemented"))
+    /// The LEDs the chroma and brightness methods accept, in the order to show them
+    fn get_leds(&self) -> &'static [LedId] {
+        &[]
     }
     async fn get_polling_rate(&self) -> Result<PollingRate> {
-        Err(anyhow!("Unimplemented"))
+        Err(Error::Unsupported)
     }
     async fn set_polling_rate(&self, _: PollingRate) -> Result<()> {
-        Err(anyhow!("Unimplemented"))
+        Err(Error::Unsupported)
     }
     async fn get_battery_level(&self) -> Result<f32> {
-        Err(anyhow!("Unimplemented"))
+        Err(Error::Unsupported)
     }
     async fn get_charging_status(&self) -> Result<bool> {
-        Err(anyhow!("Unimplemented"))
+        Err(Error::Unsupported)
     }
-    async fn chroma_logo_matrix_effect(&self, _: ExtendedMatrixEffect) -> Result<()> {
-        Err(anyhow!("Unimplemented"))
+    /// How long the mouse may sit idle before it goes to sleep
+    async fn get_idle_time(&self) -> Result<Duration> {
+        Err(Error::Unsupported)
     }
+    /// Between `RAZER_MIN_IDLE_TIME` and `RAZER_MAX_IDLE_TIME`, in whole seconds
+    async fn set_idle_time(&self, _: Duration) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+    /// Battery percentage below which the mouse starts saving power
+    async fn get_low_battery_threshold(&self) -> Result<u8> {
+        Err(Error::Unsupported)
+    }
+    /// Between `RAZER_MIN_LOW_BATTERY_THRESHOLD` and `RAZER_MAX_LOW_BATTERY_THRESHOLD` percent
+    async fn set_low_battery_threshold(&self, _: u8) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+    async fn chroma_matrix_effect(
+        &self,
+        _: LedId,
+        _: ExtendedMatrixEffect,
+        _: Persistence,
+    ) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+    async fn get_chroma_matrix_effect(
+        &self,
+        _: LedId,
+        _: Persistence,
+    ) -> Result<ExtendedMatrixEffect> {
+        Err(Error::Unsupported)
+    }
+    /// 0 (off) to 255
+    async fn get_led_brightness(&self, _: LedId, _: Persistence) -> Result<u8> {
+        Err(Error::Unsupported)
+    }
+    async fn set_led_brightness(&self, _: LedId, _: u8, _: Persistence) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RazerDevice {
+    source: DeviceSource,
+    recording: Option<PathBuf>,
+    timeout: Duration,
 }
 
-pub struct RazerDevice(DeviceInfo);
+#[derive(Clone, Debug)]
+enum DeviceSource {
+    Usb(DeviceInfo),
+    Simulated(SimulatedMouse),
+}
 
 impl RazerDevice {
     pub fn new(device_info: DeviceInfo) -> Self {
-        RazerDevice(device_info)
+        RazerDevice {
+            source: DeviceSource::Usb(device_info),
+            recording: None,
+            timeout: RAZER_DEFAULT_COMMAND_TIMEOUT,
+        }
+    }
+
+    /// A device backed by a `SimulatedMouse` instead of real hardware
+    pub fn simulated(mouse: SimulatedMouse) -> Self {
+        RazerDevice {
+            source: DeviceSource::Simulated(mouse),
+            recording: None,
+            timeout: RAZER_DEFAULT_COMMAND_TIMEOUT,
+        }
+    }
+
+    /// Append every report exchanged with the device to the JSONL file at `path`,
+    /// see `RecordingTransport`.
+    pub fn with_recording(mut self, path: impl Into<PathBuf>) -> Self {
+        self.recording = Some(path.into());
+        self
     }
 
+    /// How long commands on the claimed device may take before failing with
+    /// `Error::TransferTimeout`, `RAZER_DEFAULT_COMMAND_TIMEOUT` unless set.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        match &self.source {
+            DeviceSource::Usb(device_info) => device_info.product_string(),
+            DeviceSource::Simulated(_) => Some("Simulated Razer DeathAdder V2 Pro"),
+        }
+    }
+
+    /// Claim the device for sending commands. Must be called within a Tokio runtime.
+    ///
+    /// If the device goes away, e.g. after being replugged or its cable being swapped, it's
+    /// found again by vendor id, product id and serial number, and `DeviceEvent::Reconnected`
+    /// is sent.
     pub fn claim(&self) -> Result<RazerDeviceClaimed> {
-        let device = self.0.open()?;
-        let interface = device.detach_and_claim_interface(RAZER_USB_INTERFACE_NUMBER)?;
-        let device_impl = get_device_impl(self.0.product_id(), interface)?;
-        Ok(RazerDeviceClaimed { device_impl })
+        let (product_id, transport) = self.open()?;
+        let device = self.clone();
+        let reconnect: Reconnect = Box::new(move || Ok(device.find_again()?.open()?.1));
+        let queue = CommandQueue::new(transport, Some(reconnect), self.timeout);
+        RazerDeviceClaimed::with_queue(product_id, queue)
+    }
+
+    fn open(&self) -> Result<(u16, Box<dyn RazerTransport>)> {
+        let (product_id, mut transport): (u16, Box<dyn RazerTransport>) = match &self.source {
+            DeviceSource::Usb(device_info) => (device_info.product_id(), open_usb(device_info)?),
+            DeviceSource::Simulated(mouse) => {
+                (DEATHADDER_V_2_PRO_WIRELESS, Box::new(mouse.clone()))
+            }
+        };
+        if let Some(path) = &self.recording {
+            let log = OpenOptions::new().create(true).append(true).open(path)?;
+            transport = Box::new(RecordingTransport::new(transport, Box::new(log)));
+        }
+        Ok((product_id, transport))
     }
+
+    /// The same device as currently enumerated, its bus address changes when it's replugged.
+    /// Swapping the cable can change its product id too, e.g. between a mouse's wired and
+    /// wireless ids, so any product id defined along with it matches.
+    fn find_again(&self) -> Result<RazerDevice> {
+        let DeviceSource::Usb(device_info) = &self.source else {
+            return Ok(self.clone());
+        };
+        let product_ids = shared_product_ids(device_info.product_id());
+        let device_info = nusb::list_devices()?
+            .find(|other| {
+                other.vendor_id() == device_info.vendor_id()
+                    && (other.product_id() == device_info.product_id()
+                        || product_ids.contains(&other.product_id()))
+                    && other.serial_number() == device_info.serial_number()
+            })
+            .ok_or(Error::Disconnected)?;
+        Ok(RazerDevice {
+            source: DeviceSource::Usb(device_info),
+            ..self.clone()
+        })
+    }
+}
+
+/// Prefer the hidraw node, which keeps the kernel driver (and the pointer) working, and
+/// only fall back to detaching the kernel driver when there is none. Failing to open an
+/// existing node, e.g. `Error::Permission` without a udev rule for it, is returned.
+fn open_usb(device_info: &DeviceInfo) -> Result<Box<dyn RazerTransport>> {
+    #[cfg(target_os = "linux")]
+    if let Some(transport) =
+        crate::hidraw::HidrawTransport::open_device(device_info, RAZER_USB_INTERFACE_NUMBER)?
+    {
+        return Ok(Box::new(transport));
+    }
+
+    let device = device_info.open()?;
+    let transport = NusbTransport::detach_and_claim(device, RAZER_USB_INTERFACE_NUMBER)?;
+    Ok(Box::new(transport))
 }
 
+/// Something that happened to a claimed device outside of the commands sent to it
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum DeviceEvent {
+    /// The device stopped answering and was claimed again, its settings may have changed
+    Reconnected,
+}
+
+/// A device claimed for sending commands, which all go through one `CommandQueue` so
+/// concurrent callers sharing it can't interleave their reports.
+///
+/// Dropping it releases the transport once queued commands are done, which reattaches the
+/// kernel driver if it had to be detached so the mouse keeps working as a pointer.
 pub struct RazerDeviceClaimed {
+    product_id: u16,
     device_impl: Box<dyn FeatureSet>,
+    queue: CommandQueue,
+}
+
+impl RazerDeviceClaimed {
+    /// Drive the device with `product_id` over any transport, e.g. one that doesn't need real hardware.
+    /// Must be called within a Tokio runtime.
+    pub fn new(product_id: u16, transport: Box<dyn RazerTransport>) -> Result<Self> {
+        let queue = CommandQueue::new(transport, None, RAZER_DEFAULT_COMMAND_TIMEOUT);
+        Self::with_queue(product_id, queue)
+    }
+
+    fn with_queue(product_id: u16, queue: CommandQueue) -> Result<Self> {
+        let device_impl = get_device_impl(product_id, queue.clone())?;
+        Ok(RazerDeviceClaimed {
+            product_id,
+            device_impl,
+            queue,
+        })
+    }
+
+    /// Another handle on the same device, whose commands time out after `timeout` instead.
+    /// Commands from both handles still share one queue.
+    pub fn with_timeout(&self, timeout: Duration) -> Self {
+        Self::with_queue(self.product_id, self.queue.with_timeout(timeout))
+            .expect("The device was already claimed with this product id")
+    }
+
+    /// Events about the device, like it being reconnected
+    pub fn events(&self) -> broadcast::Receiver<DeviceEvent> {
+        self.queue.events()
+    }
+
+    /// Release the device like dropping it does, but wait for it and report a failure to
+    /// reattach the kernel driver.
+    pub async fn release(self) -> Result<()> {
+        self.queue.release().await
+    }
+}
+
+impl std::fmt::Debug for RazerDeviceClaimed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RazerDeviceClaimed").finish_non_exhaustive()
+    }
 }
 
 impl Deref for RazerDeviceClaimed {
@@ -82,161 +296,266 @@ impl Deref for RazerDeviceClaimed {
     }
 }
 
-async fn get_dpi(interface: Interface, transaction_id: u8, var_store: VarStoreId) -> Result<Dpi> {
-    let request = RazerMessageBuilder::get_dpi(var_store)
-        .with_transaction_id(transaction_id)
-        .build();
-    let response = send_razer_message_and_wait_response(interface, request).await?;
+async fn get_firmware_version(queue: &CommandQueue, transaction_id: u8) -> Result<FirmwareVersion> {
+    match queue
+        .send(transaction_id, Command::GetFirmwareVersion)
+        .await?
+    {
+        Response::FirmwareVersion(version) => Ok(version),
+        response => Err(unexpected_response(response)),
+    }
+}
+
+async fn get_serial(queue: &CommandQueue, transaction_id: u8) -> Result<String> {
+    match queue.send(transaction_id, Command::GetSerial).await? {
+        Response::Serial(serial) => Ok(serial),
+        response => Err(unexpected_response(response)),
+    }
+}
 
-    let dpi_x: u16 = decode_u16_from_bytes(&response.arguments()[1..=2]);
-    let dpi_y: u16 = decode_u16_from_bytes(&response.arguments()[3..=4]);
-    Ok((dpi_x, dpi_y).into())
+async fn get_device_mode(queue: &CommandQueue, transaction_id: u8) -> Result<DeviceMode> {
+    match queue.send(transaction_id, Command::GetDeviceMode).await? {
+        Response::DeviceMode(mode) => Ok(mode),
+        response => Err(unexpected_response(response)),
+    }
+}
+
+async fn set_device_mode(queue: &CommandQueue, transaction_id: u8, mode: DeviceMode) -> Result<()> {
+    queue
+        .send(transaction_id, Command::SetDeviceMode(mode))
+        .await?;
+    Ok(())
+}
+
+async fn get_dpi(queue: &CommandQueue, transaction_id: u8, var_store: VarStoreId) -> Result<Dpi> {
+    match queue
+        .send(transaction_id, Command::GetDpi(var_store))
+        .await?
+    {
+        // What a mouse that is queried too quickly answers, it can't really be at 0 DPI
+        Response::Dpi(Dpi { x: 0, .. } | Dpi { y: 0, .. }) => {
+            Err(Error::InvalidResponse("The mouse reported 0 DPI".into()))
+        }
+        Response::Dpi(dpi) => Ok(dpi),
+        response => Err(unexpected_response(response)),
+    }
 }
 
 async fn set_dpi(
-    interface: Interface,
+    queue: &CommandQueue,
     transaction_id: u8,
     var_store: VarStoreId,
     dpi: Dpi,
 ) -> Result<()> {
-    let request = RazerMessageBuilder::set_dpi(var_store, dpi)
-        .with_transaction_id(transaction_id)
-        .build();
-    send_razer_message(interface, request).await
-}
-
-async fn get_dpi_stages(interface: Interface, transaction_id: u8) -> Result<DpiStages> {
-    let request = RazerMessageBuilder::get_dpi_stages(VarStoreId::VarStore)
-        .with_transaction_id(transaction_id)
-        .build();
-    let response = send_razer_message_and_wait_response(interface, request).await?;
-
-    // Response format (hex):
-    // 01    varstore
-    // 02    active DPI stage (1 indexed)
-    // 04    number of stages = 4
-    //
-    // 01    first DPI stage
-    // 03 20 first stage DPI X = 800
-    // 03 20 first stage DPI Y = 800
-    // 00 00 reserved
-    //
-    // 02    second DPI stage
-    // 07 08 second stage DPI X = 1800
-    // 07 08 second stage DPI Y = 1800
-    // 00 00 reserved
-    //
-    // 03    third DPI stage
-    // ...
-
-    // 1-indexed, convert to 0-indexed
-    let active_stage = response.arguments()[1] - 1;
-    let num_stages = response.arguments()[2] as usize;
-    let result = response.arguments()[3..]
-        .chunks_exact(0x07)
-        .take(num_stages)
-        .map(|dpi_stage| {
-            let x = decode_u16_from_bytes(&dpi_stage[1..=2]);
-            let y = decode_u16_from_bytes(&dpi_stage[3..=4]);
-            Dpi { x, y }
-        })
-        .collect();
+    queue
+        .send(transaction_id, Command::SetDpi(var_store, dpi))
+        .await?;
+    Ok(())
+}
 
-    DpiStages::new(active_stage, result)
+async fn get_dpi_stages(
+    queue: &CommandQueue,
+    transaction_id: u8,
+    var_store: VarStoreId,
+) -> Result<DpiStages> {
+    let command = Command::GetDpiStages(var_store);
+    match queue.send(transaction_id, command).await? {
+        Response::DpiStages(dpi_stages) => Ok(dpi_stages),
+        response => Err(unexpected_response(response)),
+    }
 }
 
 async fn set_dpi_stages(
-    interface: Interface,
+    queue: &CommandQueue,
     transaction_id: u8,
+    var_store: VarStoreId,
     dpi_stages: &DpiStages,
 ) -> Result<()> {
-    let request = RazerMessageBuilder::set_dpi_stages(VarStoreId::VarStore, dpi_stages)
-        .with_transaction_id(transaction_id)
-        .build();
-    send_razer_message(interface, request).await
+    let command = Command::SetDpiStages(var_store, dpi_stages.clone());
+    queue.send(transaction_id, command).await?;
+    Ok(())
 }
 
-async fn get_polling_rate(interface: Interface, transaction_id: u8) -> Result<PollingRate> {
-    let request = RazerMessageBuilder::get_polling_rate()
-        .with_transaction_id(transaction_id)
-        .build();
-    let response = send_razer_message_and_wait_response(interface, request).await?;
-
-    match response.arguments()[0] {
-        0x01 => Ok(NormalPollingRate::Rate1000.into()),
-        0x02 => Ok(NormalPollingRate::Rate500.into()),
-        0x08 => Ok(NormalPollingRate::Rate125.into()),
-        _ => Err(anyhow!("Invalid polling rate response")),
+async fn get_polling_rate(
+    queue: &CommandQueue,
+    transaction_id: u8,
+    family: PollingRateFamily,
+) -> Result<PollingRate> {
+    let command = match family {
+        PollingRateFamily::Normal => Command::GetPollingRate,
+        PollingRateFamily::Extended => Command::GetPollingRateExtended,
+    };
+    match queue.send(transaction_id, command).await? {
+        Response::PollingRate(polling_rate) => Ok(polling_rate.into()),
+        Response::PollingRateExtended(polling_rate) => Ok(polling_rate.into()),
+        response => Err(unexpected_response(response)),
     }
 }
 
+/// Any `PollingRate` of a rate in `family` works, e.g. `ExtendedPollingRate::Rate1000` on a
+/// device with normal polling rates
 async fn set_polling_rate(
-    interface: Interface,
+    queue: &CommandQueue,
     transaction_id: u8,
+    family: PollingRateFamily,
     polling_rate: PollingRate,
 ) -> Result<()> {
-    match polling_rate {
-        PollingRate::Normal(polling_rate) => {
-            let request = RazerMessageBuilder::set_polling_rate(polling_rate)
-                .with_transaction_id(transaction_id)
-                .build();
-            send_razer_message(interface, request).await
-        }
-        PollingRate::Extended(_) => Err(anyhow!(
-            "Trying to use ExtendedPollingRate on a NormalPollingRate device."
-        )),
-    }
-}
-
-#[allow(unused)]
-async fn set_polling_rate_extended(interface: Interface, polling_rate: PollingRate) -> Result<()> {
-    match polling_rate {
-        PollingRate::Normal(_) => Err(anyhow!(
-            "Trying to use NormalPollingRate on an ExtendedPollingRate device."
-        )),
-        PollingRate::Extended(polling_rate) => {
-            let request = RazerMessageBuilder::set_polling_rate_extended(polling_rate)
-                .with_transaction_id(0x1f)
-                .build();
-            send_razer_message(interface, request).await
+    let command = match family.rate(polling_rate.into()) {
+        Some(PollingRate::Normal(polling_rate)) => Command::SetPollingRate(polling_rate),
+        Some(PollingRate::Extended(polling_rate)) => Command::SetPollingRateExtended(polling_rate),
+        None => {
+            let rates: Vec<String> = family.rates().iter().map(|rate| rate.to_string()).collect();
+            return Err(Error::InvalidArgument(format!(
+                "Polling rate {} isn't supported, must be one of: [{}]",
+                polling_rate,
+                rates.join(", ")
+            )));
         }
+    };
+    queue.send(transaction_id, command).await?;
+    Ok(())
+}
+
+async fn get_battery_level(queue: &CommandQueue, transaction_id: u8) -> Result<f32> {
+    match queue.send(transaction_id, Command::GetBatteryLevel).await? {
+        Response::BatteryLevel(level) => Ok(level as f32 / 255. * 100.),
+        response => Err(unexpected_response(response)),
     }
 }
 
-async fn get_battery_level(interface: Interface, transaction_id: u8) -> Result<f32> {
-    let request = RazerMessageBuilder::get_battery_level()
-        .with_transaction_id(transaction_id)
-        .build();
-    let response = send_razer_message_and_wait_response(interface, request).await?;
+async fn get_charging_status(queue: &CommandQueue, transaction_id: u8) -> Result<bool> {
+    match queue
+        .send(transaction_id, Command::GetChargingStatus)
+        .await?
+    {
+        Response::ChargingStatus(charging_status) => Ok(charging_status),
+        response => Err(unexpected_response(response)),
+    }
+}
 
-    let battery_level = response.arguments()[1] as f32 / 255. * 100.;
-    Ok(battery_level)
+async fn get_idle_time(queue: &CommandQueue, transaction_id: u8) -> Result<Duration> {
+    match queue.send(transaction_id, Command::GetIdleTime).await? {
+        Response::IdleTime(seconds) => Ok(Duration::from_secs(seconds.into())),
+        response => Err(unexpected_response(response)),
+    }
 }
 
-async fn get_charging_status(interface: Interface, transaction_id: u8) -> Result<bool> {
-    let request = RazerMessageBuilder::get_charging_status()
-        .with_transaction_id(transaction_id)
-        .build();
-    let response = send_razer_message_and_wait_response(interface, request).await?;
+async fn set_idle_time(
+    queue: &CommandQueue,
+    transaction_id: u8,
+    idle_time: Duration,
+) -> Result<()> {
+    if !(RAZER_MIN_IDLE_TIME..=RAZER_MAX_IDLE_TIME).contains(&idle_time) {
+        return Err(Error::InvalidArgument(format!(
+            "Idle time must be between {} and {} seconds",
+            RAZER_MIN_IDLE_TIME.as_secs(),
+            RAZER_MAX_IDLE_TIME.as_secs()
+        )));
+    }
+    let command = Command::SetIdleTime(idle_time.as_secs() as u16);
+    queue.send(transaction_id, command).await?;
+    Ok(())
+}
 
-    let charging_status = response.arguments()[1] > 0;
-    Ok(charging_status)
+async fn get_low_battery_threshold(queue: &CommandQueue, transaction_id: u8) -> Result<u8> {
+    match queue
+        .send(transaction_id, Command::GetLowBatteryThreshold)
+        .await?
+    {
+        // Rounded, so a percentage set before reads back the same
+        Response::LowBatteryThreshold(threshold) => {
+            Ok(((threshold as u16 * 100 + 127) / 255) as u8)
+        }
+        response => Err(unexpected_response(response)),
+    }
 }
 
-async fn chroma_logo_matrix_effect(
-    interface: Interface,
+async fn set_low_battery_threshold(
+    queue: &CommandQueue,
     transaction_id: u8,
+    percent: u8,
+) -> Result<()> {
+    if !(RAZER_MIN_LOW_BATTERY_THRESHOLD..=RAZER_MAX_LOW_BATTERY_THRESHOLD).contains(&percent) {
+        return Err(Error::InvalidArgument(format!(
+            "Low battery threshold must be between {}% and {}%",
+            RAZER_MIN_LOW_BATTERY_THRESHOLD, RAZER_MAX_LOW_BATTERY_THRESHOLD
+        )));
+    }
+    let threshold = (percent as u16 * 255 / 100) as u8;
+    let command = Command::SetLowBatteryThreshold(threshold);
+    queue.send(transaction_id, command).await?;
+    Ok(())
+}
+
+async fn chroma_matrix_effect(
+    queue: &CommandQueue,
+    transaction_id: u8,
+    var_store: VarStoreId,
+    led: LedId,
     effect: ExtendedMatrixEffect,
 ) -> Result<()> {
-    let request = RazerMessageBuilder::chroma_extended_matrix_effect(
-        VarStoreId::VarStore,
-        LedId::Logo,
-        effect,
-    )
-    .with_transaction_id(transaction_id)
-    .build();
+    let command = Command::SetMatrixEffect(var_store, led, effect);
+    queue.send(transaction_id, command).await?;
+    Ok(())
+}
+
+async fn get_chroma_matrix_effect(
+    queue: &CommandQueue,
+    transaction_id: u8,
+    var_store: VarStoreId,
+    led: LedId,
+) -> Result<ExtendedMatrixEffect> {
+    match queue
+        .send(transaction_id, Command::GetMatrixEffect(var_store, led))
+        .await?
+    {
+        Response::MatrixEffect(effect) => Ok(effect),
+        response => Err(unexpected_response(response)),
+    }
+}
 
-    send_razer_message(interface, request).await
+async fn get_led_brightness(
+    queue: &CommandQueue,
+    transaction_id: u8,
+    var_store: VarStoreId,
+    led: LedId,
+) -> Result<u8> {
+    match queue
+        .send(transaction_id, Command::GetLedBrightness(var_store, led))
+        .await?
+    {
+        Response::LedBrightness(brightness) => Ok(brightness),
+        response => Err(unexpected_response(response)),
+    }
+}
+
+async fn set_led_brightness(
+    queue: &CommandQueue,
+    transaction_id: u8,
+    var_store: VarStoreId,
+    led: LedId,
+    brightness: u8,
+) -> Result<()> {
+    let command = Command::SetLedBrightness(var_store, led, brightness);
+    queue.send(transaction_id, command).await?;
+    Ok(())
+}
+
+/// Fail for an LED the device wasn't declared with, which it would ignore or reject
+fn check_led(leds: &[LedId], led: LedId) -> Result<()> {
+    if leds.contains(&led) {
+        return Ok(());
+    }
+    let leds: Vec<String> = leds.iter().map(LedId::to_string).collect();
+    Err(Error::InvalidArgument(format!(
+        "The mouse has no {} LED, must be one of: [{}]",
+        led,
+        leds.join(", ")
+    )))
+}
+
+fn unexpected_response(response: Response) -> Error {
+    Error::InvalidResponse(format!("Unexpected response: {:?}", response))
 }
 
 device_impls!([
@@ -244,6 +563,12 @@ device_impls!([
     DeathadderV2ProWireless 0x007D
     {
         transaction_id = 0x3f,
+        polling_rate = Normal,
+        leds = [Logo],
+        get_firmware_version,
+        get_serial,
+        get_device_mode,
+        set_device_mode,
         get_dpi,
         set_dpi,
         get_dpi_stages,
@@ -252,6 +577,30 @@ device_impls!([
         set_polling_rate,
         get_battery_level,
         get_charging_status,
-        chroma_logo_matrix_effect,
+        get_idle_time,
+        set_idle_time,
+        get_low_battery_threshold,
+        set_low_battery_threshold,
+        chroma_matrix_effect,
+        get_chroma_matrix_effect,
+        get_led_brightness,
+        set_led_brightness,
+    },
+    Viper8k 0x0091 {
+        transaction_id = 0x1f,
+        polling_rate = Extended,
+        leds = [Logo],
+        get_firmware_version,
+        get_serial,
+        get_dpi,
+        set_dpi,
+        get_dpi_stages,
+        set_dpi_stages,
+        get_polling_rate,
+        set_polling_rate,
+        chroma_matrix_effect,
+        get_chroma_matrix_effect,
+        get_led_brightness,
+        set_led_brightness,
     },
 ]);
@@ -0,0 +1,151 @@
+use std::fmt::Write;
+
+use zerocopy::FromBytes;
+
+use crate::{
+    common::{RazerMessage, RazerStatus, RAZER_REPORT_SIZE},
+    protocol::{command_name, Command, Response},
+    Error, Result,
+};
+
+/// Describe a raw `RAZER_REPORT_SIZE` byte report (request or response) in human readable form:
+/// status, transaction id, command with its name if known, decoded arguments and whether the
+/// CRC checks out.
+pub fn dissect_report(report: &[u8]) -> Result<String> {
+    let message = RazerMessage::read_from_bytes(report).map_err(|_| {
+        Error::InvalidArgument(format!(
+            "Report must be {} bytes, got {}",
+            RAZER_REPORT_SIZE,
+            report.len()
+        ))
+    })?;
+
+    let mut out = String::new();
+    // Writing to a String can't fail
+    let _ = write_dissection(&mut out, &message);
+    Ok(out)
+}
+
+/// Parse a hex dump like `00 3f 00 00`, `00:3f:00:00`, `0x00, 0x3f` or `003f0000` into bytes.
+pub fn parse_hex(hex: &str) -> Result<Vec<u8>> {
+    let digits: String = hex
+        .split(|c: char| c.is_whitespace() || matches!(c, ':' | '-' | ','))
+        .map(|group| {
+            group
+                .strip_prefix("0x")
+                .or_else(|| group.strip_prefix("0X"))
+                .unwrap_or(group)
+        })
+        .collect();
+    if !digits.len().is_multiple_of(2) {
+        return Err(Error::InvalidArgument("Odd number of hex digits".into()));
+    }
+
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&digits[i..i + 2], 16)
+                .map_err(|_| Error::InvalidArgument(format!("Invalid hex: {}", &digits[i..i + 2])))
+        })
+        .collect()
+}
+
+fn write_dissection(out: &mut String, message: &RazerMessage) -> std::fmt::Result {
+    let status = RazerStatus::try_from(message.status());
+    let status_name = match status {
+        Ok(status) => format!("{:?}", status),
+        Err(_) => "Unknown".into(),
+    };
+    writeln!(
+        out,
+        "Status:      {} ({:#04x})",
+        status_name,
+        message.status()
+    )?;
+    writeln!(out, "Transaction: {:#04x}", message.transaction_id())?;
+
+    let (class, id) = (message.command_class(), message.command_id());
+    let name = command_name(class, id).unwrap_or("Unknown command");
+    writeln!(out, "Command:     {:#04x}:{:#04x} ({})", class, id, name)?;
+
+    let data_size = (message.data_size() as usize).min(message.arguments().len());
+    let arguments: Vec<String> = message.arguments()[..data_size]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    writeln!(out, "Arguments:   [{}] {}", data_size, arguments.join(" "))?;
+
+    if let Some(decoded) = decode_arguments(status, message) {
+        writeln!(out, "Decoded:     {}", decoded)?;
+    }
+
+    let expected_crc = message.expected_crc();
+    if message.crc() == expected_crc {
+        write!(out, "CRC:         {:#04x} (ok)", message.crc())
+    } else {
+        write!(
+            out,
+            "CRC:         {:#04x} (bad, expected {:#04x})",
+            message.crc(),
+            expected_crc
+        )
+    }
+}
+
+/// Requests decode as the command that was sent. Responses decode as the answer, except
+/// for responses to SET commands, which just echo the request's arguments.
+fn decode_arguments(
+    status: std::result::Result<RazerStatus, u8>,
+    message: &RazerMessage,
+) -> Option<String> {
+    if status != Ok(RazerStatus::New) {
+        match Response::decode(message) {
+            Ok(Response::Ack) => {}
+            Ok(response) => return Some(format!("{:?}", response)),
+            Err(_) => return None,
+        }
+    }
+    Command::decode(message)
+        .ok()
+        .map(|command| format!("{:?}", command))
+}
+
+#[cfg(test)]
+mod tests {
+    use zerocopy::IntoBytes;
+
+    use super::*;
+    use crate::common::{Dpi, VarStoreId};
+
+    #[test]
+    fn dissect_dpi_response() {
+        let mut message = Command::GetDpi(VarStoreId::NoStore)
+            .encode()
+            .with_transaction_id(0x3f)
+            .build();
+        message.set_status(RazerStatus::Success);
+        Response::Dpi(Dpi { x: 1600, y: 800 }).encode(&mut message);
+        let mut report = message.as_bytes().to_vec();
+
+        let dissection = dissect_report(&report).unwrap();
+        assert!(dissection.contains("Success (0x02)"));
+        assert!(dissection.contains("(Get DPI)"));
+        assert!(dissection.contains("Dpi(Dpi { x: 1600, y: 800 })"));
+        assert!(dissection.contains("(bad, expected"));
+
+        report[RAZER_REPORT_SIZE - 2] = message.expected_crc();
+        assert!(dissect_report(&report).unwrap().contains("(ok)"));
+    }
+
+    #[test]
+    fn parse_hex_formats() {
+        assert_eq!(parse_hex("00 3f:A0").unwrap(), vec![0x00, 0x3f, 0xa0]);
+        assert_eq!(parse_hex("0x003fa0").unwrap(), vec![0x00, 0x3f, 0xa0]);
+        assert_eq!(
+            parse_hex("0x00, 0x3f 0XA0").unwrap(),
+            vec![0x00, 0x3f, 0xa0]
+        );
+        assert!(parse_hex("03f").is_err());
+        assert!(parse_hex("zz").is_err());
+    }
+}
@@ -0,0 +1,79 @@
+use std::{fmt::Display, time::Duration};
+
+use nusb::transfer::TransferError;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The device (or the driver for it) doesn't support this feature
+    Unsupported,
+    /// The device is gone, e.g. unplugged, and couldn't be found again
+    Disconnected,
+    /// Opening or claiming the USB device failed
+    Usb(std::io::Error),
+    /// A USB transfer to or from the device failed
+    Transfer(TransferError),
+    /// Not allowed to open the device, usually because of missing udev rules
+    Permission,
+    /// The device stayed busy and never answered the request
+    Busy,
+    /// The device timed out processing the request
+    Timeout,
+    /// The device didn't answer within the command timeout, e.g. because it was unplugged
+    /// or a wireless mouse is asleep
+    TransferTimeout(Duration),
+    /// The device answered the request with a failure status
+    DeviceRejected { status: u8 },
+    /// The device answered with a response that can't be decoded
+    InvalidResponse(String),
+    /// The request can't be sent with these arguments
+    InvalidArgument(String),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Unsupported => f.write_str("Unsupported by this device"),
+            Error::Disconnected => f.write_str("Device disconnected"),
+            Error::Usb(err) => write!(f, "USB error: {}", err),
+            Error::Transfer(err) => write!(f, "USB transfer failed: {}", err),
+            Error::Permission => f.write_str("Permission denied opening the device"),
+            Error::Busy => f.write_str("Device stayed busy"),
+            Error::Timeout => f.write_str("Device timed out"),
+            Error::TransferTimeout(timeout) => {
+                write!(f, "Device didn't answer within {:?}", timeout)
+            }
+            Error::DeviceRejected { status } => {
+                write!(f, "Device rejected the request (status {:#04x})", status)
+            }
+            Error::InvalidResponse(msg) => write!(f, "Invalid response: {}", msg),
+            Error::InvalidArgument(msg) => write!(f, "Invalid argument: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Usb(err) => Some(err),
+            Error::Transfer(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        match err.kind() {
+            std::io::ErrorKind::PermissionDenied => Error::Permission,
+            _ => Error::Usb(err),
+        }
+    }
+}
+
+impl From<TransferError> for Error {
+    fn from(err: TransferError) -> Self {
+        Error::Transfer(err)
+    }
+}
@@ -0,0 +1,145 @@
+//! Feature reports through `/dev/hidrawN`, which leaves the kernel HID driver bound.
+//!
+//! Detaching usbhid to claim the interface with `NusbTransport` stops the pointer for a
+//! couple of seconds, so `RazerDevice::claim` prefers this backend whenever the device
+//! has a hidraw node we can open.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use nusb::DeviceInfo;
+
+use crate::{common::RAZER_REPORT_SIZE, transport::RazerTransport, Error, Result};
+
+/// Razer devices don't use numbered reports, the ioctls still expect the report ID byte.
+const REPORT_ID: u8 = 0x00;
+const FEATURE_REPORT_SIZE: usize = RAZER_REPORT_SIZE + 1;
+
+/// `_IOC(_IOC_READ | _IOC_WRITE, 'H', nr, len)` from `linux/hidraw.h`
+const fn hidraw_ioctl(nr: u64, len: usize) -> u64 {
+    const IOC_READ_WRITE: u64 = 3;
+    (IOC_READ_WRITE << 30) | ((len as u64) << 16) | ((b'H' as u64) << 8) | nr
+}
+
+const HIDIOCSFEATURE: u64 = hidraw_ioctl(0x06, FEATURE_REPORT_SIZE);
+const HIDIOCGFEATURE: u64 = hidraw_ioctl(0x07, FEATURE_REPORT_SIZE);
+
+/// Sends reports as HID feature reports on a hidraw node.
+#[derive(Debug)]
+pub struct HidrawTransport(Arc<File>);
+
+impl HidrawTransport {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        Ok(HidrawTransport(Arc::new(file)))
+    }
+
+    /// Open the hidraw node of `interface_number` on `device_info`, `None` if the kernel
+    /// didn't create one.
+    pub fn open_device(device_info: &DeviceInfo, interface_number: u8) -> Result<Option<Self>> {
+        find_hidraw_node(device_info, interface_number)?
+            .map(|path| Self::open(&path))
+            .transpose()
+    }
+}
+
+#[async_trait]
+impl RazerTransport for HidrawTransport {
+    async fn send_report(&self, report: &[u8]) -> Result<()> {
+        if report.len() != RAZER_REPORT_SIZE {
+            return Err(Error::InvalidArgument(format!(
+                "Feature reports are {} bytes, got {}",
+                RAZER_REPORT_SIZE,
+                report.len()
+            )));
+        }
+        let mut buffer = [0u8; FEATURE_REPORT_SIZE];
+        buffer[0] = REPORT_ID;
+        buffer[1..].copy_from_slice(report);
+
+        let file = self.0.clone();
+        spawn_blocking(move || feature_ioctl(&file, HIDIOCSFEATURE, &mut buffer)).await?;
+        Ok(())
+    }
+
+    async fn receive_report(&self) -> Result<Vec<u8>> {
+        let file = self.0.clone();
+        spawn_blocking(move || {
+            let mut buffer = [0u8; FEATURE_REPORT_SIZE];
+            buffer[0] = REPORT_ID;
+            let len = feature_ioctl(&file, HIDIOCGFEATURE, &mut buffer)?;
+            // The returned length includes the report ID byte
+            Ok(buffer[1..len.clamp(1, FEATURE_REPORT_SIZE)].to_vec())
+        })
+        .await
+    }
+}
+
+async fn spawn_blocking<T: Send + 'static>(
+    f: impl FnOnce() -> Result<T> + Send + 'static,
+) -> Result<T> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|err| Error::Usb(std::io::Error::other(err)))?
+}
+
+fn feature_ioctl(
+    file: &File,
+    request: u64,
+    buffer: &mut [u8; FEATURE_REPORT_SIZE],
+) -> Result<usize> {
+    // SAFETY: `request` encodes the length of `buffer`, which outlives the call.
+    let len = unsafe { libc::ioctl(file.as_raw_fd(), request as _, buffer.as_mut_ptr()) };
+    if len < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(len as usize)
+}
+
+/// Find `/dev/hidrawN` for an interface by walking sysfs: each
+/// `/sys/class/hidraw/hidrawN/device` links to a HID device below its USB interface.
+fn find_hidraw_node(device_info: &DeviceInfo, interface_number: u8) -> Result<Option<PathBuf>> {
+    let device_path = fs::canonicalize(device_info.sysfs_path())?;
+    let entries = match fs::read_dir("/sys/class/hidraw") {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+
+    for entry in entries {
+        let entry = entry?;
+        let Ok(hid_path) = fs::canonicalize(entry.path().join("device")) else {
+            continue;
+        };
+        let Some(interface_path) = hid_path.parent() else {
+            continue;
+        };
+        if interface_path.parent() != Some(device_path.as_path()) {
+            continue;
+        }
+        let number = fs::read_to_string(interface_path.join("bInterfaceNumber"))
+            .ok()
+            .and_then(|number| u8::from_str_radix(number.trim(), 16).ok());
+        if number == Some(interface_number) {
+            return Ok(Some(Path::new("/dev").join(entry.file_name())));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ioctl_numbers_match_linux_headers() {
+        // HIDIOCSFEATURE(91) and HIDIOCGFEATURE(91) as computed by the C macros
+        assert_eq!(HIDIOCSFEATURE, 0xC05B_4806);
+        assert_eq!(HIDIOCGFEATURE, 0xC05B_4807);
+    }
+}
@@ -1,7 +1,496 @@
 pub mod batched;
+pub mod capture;
 pub mod chroma;
 pub mod common;
 pub mod devices;
+pub mod dissector;
+mod error;
+#[cfg(target_os = "linux")]
+pub mod hidraw;
+mod protocol;
+mod queue;
+pub mod recording;
+pub mod simulator;
+pub mod transport;
+
+pub use error::{Error, Result};
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use async_trait::async_trait;
+    use nusb::transfer::TransferError;
+
+    use crate::{
+        batched::{BatchedFeatureSet, DeviceSettings},
+        chroma::{Color, ExtendedMatrixEffect, LedId},
+        common::{
+            Dpi, DpiStages, ExtendedPollingRate, NormalPollingRate, Persistence, PollingRateFamily,
+        },
+        devices::{
+            DeviceEvent, FeatureSet, RazerDevice, RazerDeviceClaimed, DEATHADDER_V_2_PRO_WIRELESS,
+            VIPER_8_K,
+        },
+        recording::ReplayTransport,
+        simulator::{SimulatedFault, SimulatedMouse, SimulatedState},
+        transport::RazerTransport,
+        Error, Result,
+    };
+
+    /// Counts the requests sent to a `SimulatedMouse`
+    struct CountingTransport(SimulatedMouse, Arc<AtomicUsize>);
+
+    #[async_trait]
+    impl RazerTransport for CountingTransport {
+        async fn send_report(&self, report: &[u8]) -> Result<()> {
+            self.1.fetch_add(1, Ordering::SeqCst);
+            self.0.send_report(report).await
+        }
+
+        async fn receive_report(&self) -> Result<Vec<u8>> {
+            self.0.receive_report().await
+        }
+    }
+
+    #[tokio::test]
+    async fn simulated_dpi_round_trip() {
+        let mouse = SimulatedMouse::default();
+        let device = RazerDevice::simulated(mouse.clone()).claim().unwrap();
+
+        device
+            .set_dpi((1600, 800).into(), Persistence::Temporary)
+            .await
+            .unwrap();
+        assert_eq!(
+            device.get_dpi(Persistence::Temporary).await.unwrap(),
+            Dpi { x: 1600, y: 800 }
+        );
+        assert_eq!(mouse.state().dpi, Dpi { x: 1600, y: 800 });
+    }
+
+    #[tokio::test]
+    async fn temporary_dpi_keeps_onboard_memory() {
+        let mouse = SimulatedMouse::default();
+        let device = RazerDevice::simulated(mouse.clone()).claim().unwrap();
+
+        device
+            .set_dpi(1600.into(), Persistence::Temporary)
+            .await
+            .unwrap();
+        assert_eq!(
+            device.get_dpi(Persistence::OnboardMemory).await.unwrap(),
+            800.into()
+        );
+
+        device
+            .set_dpi(3200.into(), Persistence::OnboardMemory)
+            .await
+            .unwrap();
+        assert_eq!(
+            device.get_dpi(Persistence::Temporary).await.unwrap(),
+            3200.into()
+        );
+        assert_eq!(mouse.state().onboard_dpi, 3200.into());
+    }
+
+    #[tokio::test]
+    async fn simulated_batched_get_and_set() {
+        let mouse = SimulatedMouse::new(SimulatedState {
+            battery_level: 0x80,
+            charging: true,
+            ..Default::default()
+        });
+        let device = RazerDevice::simulated(mouse.clone()).claim().unwrap();
+
+        let dpi_stages = DpiStages::new(0, vec![500.into(), (1000, 2000).into()]).unwrap();
+        let color = Color {
+            r: 0xff,
+            g: 0x80,
+            b: 0x00,
+        };
+        let settings = DeviceSettings {
+            persistence: Persistence::Temporary,
+            dpi: Some(1200.into()),
+            dpi_stages: Some(dpi_stages.clone()),
+            polling_rate: Some(NormalPollingRate::Rate500.into()),
+            idle_time: Some(Duration::from_secs(600)),
+            low_battery_threshold: Some(15),
+            led_effects: [(LedId::Logo, ExtendedMatrixEffect::Reactive(color, 0x03))].into(),
+            led_brightness: [(LedId::Logo, 0x40)].into(),
+        };
+        device.set_batched(&settings).await.unwrap();
+
+        let info = device.get_batched(Persistence::Temporary).await;
+        assert_eq!(info.dpi, settings.dpi);
+        assert_eq!(info.dpi_stages, Some(dpi_stages));
+        assert_eq!(info.polling_rate, settings.polling_rate);
+        assert_eq!(info.charging_status, Some(true));
+        assert_eq!(info.idle_time, settings.idle_time);
+        assert_eq!(info.low_battery_threshold, Some(15));
+        assert_eq!(mouse.state().low_battery_threshold, 0x26);
+        assert_eq!(info.led_effects, settings.led_effects);
+        assert_eq!(info.led_brightness, settings.led_brightness);
+        assert_eq!(info.serial.as_deref(), Some("PM2049H08310000"));
+        assert_eq!(info.firmware_version.unwrap().to_string(), "v1.4");
+
+        // Onboard memory still has what the mouse starts with
+        let onboard = device.get_batched(Persistence::OnboardMemory).await;
+        let factory = SimulatedState::default();
+        assert_eq!(onboard.dpi, settings.dpi);
+        assert_eq!(onboard.dpi_stages, Some(factory.onboard_dpi_stages));
+        assert_eq!(onboard.led_effects, factory.onboard_led_effects);
+        assert_eq!(onboard.led_brightness, factory.onboard_led_brightness);
+        assert!(info
+            .battery_level
+            .is_some_and(|level| (level - 50.2).abs() < 0.1));
+    }
+
+    #[tokio::test]
+    async fn simulated_busy_responses_are_retried() {
+        let mouse = SimulatedMouse::default();
+        let device = RazerDevice::simulated(mouse.clone()).claim().unwrap();
+
+        mouse.inject_fault(SimulatedFault::Busy);
+        mouse.inject_fault(SimulatedFault::Busy);
+        assert_eq!(
+            device.get_dpi(Persistence::Temporary).await.unwrap(),
+            800.into()
+        );
+
+        for _ in 0..20 {
+            mouse.inject_fault(SimulatedFault::Busy);
+        }
+        assert!(matches!(
+            device.get_dpi(Persistence::Temporary).await,
+            Err(Error::Busy)
+        ));
+    }
+
+    #[tokio::test]
+    async fn simulated_stale_and_corrupt_responses_are_rejected() {
+        let mouse = SimulatedMouse::default();
+        let device = RazerDevice::simulated(mouse.clone()).claim().unwrap();
+        device
+            .set_dpi(1800.into(), Persistence::Temporary)
+            .await
+            .unwrap();
+
+        mouse.inject_fault(SimulatedFault::StaleResponse);
+        mouse.inject_fault(SimulatedFault::CorruptCrc);
+        assert_eq!(
+            device.get_polling_rate().await.unwrap(),
+            NormalPollingRate::Rate1000.into()
+        );
+        assert_eq!(
+            device.get_dpi(Persistence::Temporary).await.unwrap(),
+            1800.into()
+        );
+    }
+
+    #[tokio::test]
+    async fn simulated_zero_dpi_reads_are_rejected() {
+        let mouse = SimulatedMouse::default();
+        let device = RazerDevice::simulated(mouse.clone()).claim().unwrap();
+
+        mouse.inject_fault(SimulatedFault::ZeroDpi);
+        assert!(matches!(
+            device.get_dpi(Persistence::Temporary).await,
+            Err(Error::InvalidResponse(_))
+        ));
+        assert_eq!(
+            device.get_dpi(Persistence::Temporary).await.unwrap(),
+            800.into()
+        );
+    }
+
+    #[tokio::test]
+    async fn polling_rates_use_the_device_family() {
+        let device = RazerDevice::simulated(SimulatedMouse::default())
+            .claim()
+            .unwrap();
+        assert_eq!(
+            device.get_polling_rate_family(),
+            Some(PollingRateFamily::Normal)
+        );
+        device
+            .set_polling_rate(ExtendedPollingRate::Rate500.into())
+            .await
+            .unwrap();
+        assert_eq!(
+            device.get_polling_rate().await.unwrap(),
+            NormalPollingRate::Rate500.into()
+        );
+        assert!(matches!(
+            device
+                .set_polling_rate(ExtendedPollingRate::Rate8000.into())
+                .await,
+            Err(Error::InvalidArgument(_))
+        ));
+
+        let mouse = SimulatedMouse::new(SimulatedState {
+            polling_rate: ExtendedPollingRate::Rate1000.into(),
+            ..Default::default()
+        });
+        let device = RazerDeviceClaimed::new(VIPER_8_K, Box::new(mouse.clone())).unwrap();
+        assert_eq!(
+            device.get_polling_rate_family(),
+            Some(PollingRateFamily::Extended)
+        );
+        device
+            .set_polling_rate(ExtendedPollingRate::Rate8000.into())
+            .await
+            .unwrap();
+        assert_eq!(
+            device.get_polling_rate().await.unwrap(),
+            ExtendedPollingRate::Rate8000.into()
+        );
+        assert_eq!(
+            mouse.state().polling_rate,
+            ExtendedPollingRate::Rate8000.into()
+        );
+    }
+
+    #[tokio::test]
+    async fn leds_are_declared_per_device() {
+        let mouse = SimulatedMouse::default();
+        let device = RazerDevice::simulated(mouse.clone()).claim().unwrap();
+        assert_eq!(device.get_leds(), [LedId::Logo]);
+        device
+            .chroma_matrix_effect(
+                LedId::Logo,
+                ExtendedMatrixEffect::None,
+                Persistence::Temporary,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            mouse.state().led_effects[&LedId::Logo],
+            ExtendedMatrixEffect::None
+        );
+        assert!(matches!(
+            device
+                .chroma_matrix_effect(
+                    LedId::ScrollWheel,
+                    ExtendedMatrixEffect::Spectrum,
+                    Persistence::Temporary,
+                )
+                .await,
+            Err(Error::InvalidArgument(_))
+        ));
+
+        let device =
+            RazerDeviceClaimed::new(VIPER_8_K, Box::new(SimulatedMouse::default())).unwrap();
+        assert_eq!(device.get_leds(), [LedId::Logo]);
+        assert_eq!(
+            device
+                .get_led_brightness(LedId::Logo, Persistence::Temporary)
+                .await
+                .unwrap(),
+            0xFF
+        );
+
+        // What `device_impls!` generates for a device without `leds = [..]`
+        struct NoLeds;
+        #[async_trait]
+        impl FeatureSet for NoLeds {}
+        let device: &dyn FeatureSet = &NoLeds;
+        assert!(device.get_leds().is_empty());
+        assert!(matches!(
+            device
+                .get_led_brightness(LedId::Logo, Persistence::Temporary)
+                .await,
+            Err(Error::Unsupported)
+        ));
+    }
+
+    #[tokio::test]
+    async fn recorded_traffic_replays() {
+        let path =
+            std::env::temp_dir().join(format!("ruzer-recording-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mouse = SimulatedMouse::default();
+        let device = RazerDevice::simulated(mouse.clone()).with_recording(&path);
+        let recorded = device.claim().unwrap();
+        mouse.inject_fault(SimulatedFault::Busy);
+        recorded
+            .set_dpi(1200.into(), Persistence::Temporary)
+            .await
+            .unwrap();
+        assert_eq!(
+            recorded.get_dpi(Persistence::Temporary).await.unwrap(),
+            1200.into()
+        );
+
+        let recording = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let replay = ReplayTransport::from_jsonl(recording.as_slice()).unwrap();
+        let replayed =
+            RazerDeviceClaimed::new(DEATHADDER_V_2_PRO_WIRELESS, Box::new(replay)).unwrap();
+        replayed
+            .set_dpi(1200.into(), Persistence::Temporary)
+            .await
+            .unwrap();
+        assert_eq!(
+            replayed.get_dpi(Persistence::Temporary).await.unwrap(),
+            1200.into()
+        );
+
+        // Traffic that diverges from the recording is rejected
+        let replay = ReplayTransport::from_jsonl(recording.as_slice()).unwrap();
+        let replayed =
+            RazerDeviceClaimed::new(DEATHADDER_V_2_PRO_WIRELESS, Box::new(replay)).unwrap();
+        assert!(matches!(
+            replayed.set_dpi(800.into(), Persistence::Temporary).await,
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn concurrent_commands_are_serialized() {
+        let mouse = SimulatedMouse::default();
+        let device = RazerDevice::simulated(mouse.clone()).claim().unwrap();
+
+        let (info, set_dpi, dpi_stages) = tokio::join!(
+            device.get_batched(Persistence::Temporary),
+            device.set_dpi(1200.into(), Persistence::Temporary),
+            device.get_dpi_stages(Persistence::OnboardMemory)
+        );
+        set_dpi.unwrap();
+        assert!(info.dpi.is_some() && info.polling_rate.is_some());
+        assert_eq!(dpi_stages.unwrap(), mouse.state().onboard_dpi_stages);
+        assert_eq!(mouse.state().dpi, 1200.into());
+    }
+
+    #[tokio::test]
+    async fn queued_writes_are_coalesced() {
+        let mouse = SimulatedMouse::default();
+        let requests = Arc::new(AtomicUsize::new(0));
+        let transport = CountingTransport(mouse.clone(), requests.clone());
+        let device =
+            RazerDeviceClaimed::new(DEATHADDER_V_2_PRO_WIRELESS, Box::new(transport)).unwrap();
+
+        let results = tokio::join!(
+            device.set_dpi(1000.into(), Persistence::Temporary),
+            device.set_polling_rate(NormalPollingRate::Rate500.into()),
+            device.set_dpi(1100.into(), Persistence::Temporary),
+            device.set_dpi(1200.into(), Persistence::Temporary),
+        );
+        results.0.unwrap();
+        results.1.unwrap();
+        results.2.unwrap();
+        results.3.unwrap();
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        assert_eq!(mouse.state().dpi, 1200.into());
+
+        // A read in between observes the first write, so both are sent
+        let (first, read, second) = tokio::join!(
+            device.set_dpi(400.into(), Persistence::Temporary),
+            device.get_dpi(Persistence::Temporary),
+            device.set_dpi(800.into(), Persistence::Temporary)
+        );
+        first.unwrap();
+        second.unwrap();
+        assert_eq!(read.unwrap(), 400.into());
+        assert_eq!(requests.load(Ordering::SeqCst), 5);
+
+        device.release().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn hung_transfers_time_out() {
+        let mouse = SimulatedMouse::default();
+        let device = RazerDevice::simulated(mouse.clone())
+            .with_timeout(Duration::from_millis(50))
+            .claim()
+            .unwrap();
+
+        let mut events = device.events();
+
+        // Not retried, an asleep mouse doesn't wake up from reconnecting
+        mouse.inject_fault(SimulatedFault::Hang);
+        assert!(matches!(
+            device.get_dpi(Persistence::Temporary).await,
+            Err(Error::TransferTimeout(timeout)) if timeout == Duration::from_millis(50)
+        ));
+        assert_eq!(
+            device.get_dpi(Persistence::Temporary).await.unwrap(),
+            800.into()
+        );
+        assert!(events.try_recv().is_err());
+
+        // Overriding the timeout for some commands
+        mouse.inject_fault(SimulatedFault::Hang);
+        let patient = device.with_timeout(Duration::from_millis(100));
+        assert!(matches!(
+            patient.set_dpi(1600.into(), Persistence::Temporary).await,
+            Err(Error::TransferTimeout(timeout)) if timeout == Duration::from_millis(100)
+        ));
+
+        // Cancelling a command that hasn't started yet skips it
+        mouse.inject_fault(SimulatedFault::Hang);
+        let hung = device.get_dpi(Persistence::Temporary);
+        let cancelled = device.set_dpi(3200.into(), Persistence::Temporary);
+        let _ = tokio::time::timeout(Duration::from_millis(10), async {
+            tokio::join!(hung, cancelled)
+        })
+        .await;
+        assert_eq!(
+            device.get_dpi(Persistence::Temporary).await.unwrap(),
+            800.into()
+        );
+
+        // A cancelled write doesn't override the one queued before it
+        mouse.inject_fault(SimulatedFault::Hang);
+        let hung = device.get_dpi(Persistence::Temporary);
+        let first = device.set_dpi(1200.into(), Persistence::Temporary);
+        let cancelled = device.set_dpi(3200.into(), Persistence::Temporary);
+        let (_, first, _) = tokio::join!(
+            hung,
+            first,
+            tokio::time::timeout(Duration::from_millis(10), cancelled)
+        );
+        first.unwrap();
+        assert_eq!(mouse.state().dpi, 1200.into());
+    }
+
+    #[tokio::test]
+    async fn dead_transports_reconnect() {
+        let mouse = SimulatedMouse::default();
+        let device = RazerDevice::simulated(mouse.clone()).claim().unwrap();
+        let mut events = device.events();
+
+        mouse.inject_fault(SimulatedFault::Disconnect);
+        device
+            .set_dpi(1600.into(), Persistence::Temporary)
+            .await
+            .unwrap();
+        assert_eq!(mouse.state().dpi, 1600.into());
+        assert_eq!(events.try_recv().unwrap(), DeviceEvent::Reconnected);
+
+        // Only retried once
+        mouse.inject_fault(SimulatedFault::Disconnect);
+        mouse.inject_fault(SimulatedFault::Disconnect);
+        assert!(matches!(
+            device.get_dpi(Persistence::Temporary).await,
+            Err(Error::Transfer(TransferError::Disconnected))
+        ));
+        assert_eq!(
+            device.get_dpi(Persistence::Temporary).await.unwrap(),
+            1600.into()
+        );
+
+        // Device errors don't reconnect
+        mouse.inject_fault(SimulatedFault::CorruptCrc);
+        device.get_dpi(Persistence::Temporary).await.unwrap();
+        assert_eq!(events.try_recv().unwrap(), DeviceEvent::Reconnected);
+        assert!(events.try_recv().is_err());
+    }
+}
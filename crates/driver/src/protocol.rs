@@ -0,0 +1,1046 @@
+use crate::{
+    chroma::{
+        BreathingEffect, Color, ExtendedMatrixEffect, LedId, StarlightEffect, WaveDirection,
+        WheelDirection,
+    },
+    common::{
+        clamp, decode_u16_from_bytes, encode_u16_as_bytes, DeviceMode, Dpi, DpiStages,
+        ExtendedPollingRate, FirmwareVersion, NormalPollingRate, RazerMessage, RazerMessageBuilder,
+        VarStoreId, RAZER_MAX_IDLE_TIME, RAZER_MIN_IDLE_TIME, RAZER_MOUSE_MAX_DPI,
+        RAZER_MOUSE_MIN_DPI,
+    },
+    Error, Result,
+};
+
+/// Length of the serial number field, in bytes
+const RAZER_SERIAL_SIZE: usize = 0x16;
+const RAZER_MIN_IDLE_SECONDS: u16 = RAZER_MIN_IDLE_TIME.as_secs() as u16;
+const RAZER_MAX_IDLE_SECONDS: u16 = RAZER_MAX_IDLE_TIME.as_secs() as u16;
+/// 5% and 25% of a full battery
+const RAZER_MIN_LOW_BATTERY_THRESHOLD_RAW: u8 = 0x0C;
+const RAZER_MAX_LOW_BATTERY_THRESHOLD_RAW: u8 = 0x3F;
+
+/// Every request the driver knows how to send. Each one maps to exactly one command class/id
+/// pair, encoded by a `RazerMessageBuilder` constructor and decoded by `Command::decode`.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Command {
+    GetFirmwareVersion,
+    GetSerial,
+    GetDeviceMode,
+    SetDeviceMode(DeviceMode),
+    GetBatteryLevel,
+    GetChargingStatus,
+    /// Seconds
+    GetIdleTime,
+    SetIdleTime(u16),
+    GetLowBatteryThreshold,
+    /// Raw battery level, 0-255
+    SetLowBatteryThreshold(u8),
+    GetDpi(VarStoreId),
+    SetDpi(VarStoreId, Dpi),
+    GetDpiStages(VarStoreId),
+    SetDpiStages(VarStoreId, DpiStages),
+    GetPollingRate,
+    SetPollingRate(NormalPollingRate),
+    GetPollingRateExtended,
+    SetPollingRateExtended(ExtendedPollingRate),
+    SetMatrixEffect(VarStoreId, LedId, ExtendedMatrixEffect),
+    GetMatrixEffect(VarStoreId, LedId),
+    GetLedBrightness(VarStoreId, LedId),
+    SetLedBrightness(VarStoreId, LedId, u8),
+}
+
+/// Decoded device responses, one for each `Command`.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Response {
+    FirmwareVersion(FirmwareVersion),
+    Serial(String),
+    DeviceMode(DeviceMode),
+    /// Raw battery level, 0-255
+    BatteryLevel(u8),
+    ChargingStatus(bool),
+    /// Seconds
+    IdleTime(u16),
+    /// Raw battery level, 0-255
+    LowBatteryThreshold(u8),
+    Dpi(Dpi),
+    DpiStages(DpiStages),
+    PollingRate(NormalPollingRate),
+    PollingRateExtended(ExtendedPollingRate),
+    MatrixEffect(ExtendedMatrixEffect),
+    LedBrightness(u8),
+    /// Response to a SET command, which just echoes the request
+    Ack,
+}
+
+impl Command {
+    pub(crate) fn encode(&self) -> RazerMessageBuilder {
+        match self {
+            Command::GetFirmwareVersion => RazerMessageBuilder::get_firmware_version(),
+            Command::GetSerial => RazerMessageBuilder::get_serial(),
+            Command::GetDeviceMode => RazerMessageBuilder::get_device_mode(),
+            Command::SetDeviceMode(mode) => RazerMessageBuilder::set_device_mode(*mode),
+            Command::GetBatteryLevel => RazerMessageBuilder::get_battery_level(),
+            Command::GetChargingStatus => RazerMessageBuilder::get_charging_status(),
+            Command::GetIdleTime => RazerMessageBuilder::get_idle_time(),
+            Command::SetIdleTime(seconds) => RazerMessageBuilder::set_idle_time(*seconds),
+            Command::GetLowBatteryThreshold => RazerMessageBuilder::get_low_battery_threshold(),
+            Command::SetLowBatteryThreshold(threshold) => {
+                RazerMessageBuilder::set_low_battery_threshold(*threshold)
+            }
+            Command::GetDpi(var_store) => RazerMessageBuilder::get_dpi(*var_store),
+            Command::SetDpi(var_store, dpi) => RazerMessageBuilder::set_dpi(*var_store, *dpi),
+            Command::GetDpiStages(var_store) => RazerMessageBuilder::get_dpi_stages(*var_store),
+            Command::SetDpiStages(var_store, dpi_stages) => {
+                RazerMessageBuilder::set_dpi_stages(*var_store, dpi_stages)
+            }
+            Command::GetPollingRate => RazerMessageBuilder::get_polling_rate(),
+            Command::SetPollingRate(polling_rate) => {
+                RazerMessageBuilder::set_polling_rate(*polling_rate)
+            }
+            Command::GetPollingRateExtended => RazerMessageBuilder::get_polling_rate_extended(),
+            Command::SetPollingRateExtended(polling_rate) => {
+                RazerMessageBuilder::set_polling_rate_extended(*polling_rate)
+            }
+            Command::SetMatrixEffect(var_store, led_id, effect) => {
+                RazerMessageBuilder::chroma_extended_matrix_effect(*var_store, *led_id, *effect)
+            }
+            Command::GetMatrixEffect(var_store, led_id) => {
+                RazerMessageBuilder::get_extended_matrix_effect(*var_store, *led_id)
+            }
+            Command::GetLedBrightness(var_store, led_id) => {
+                RazerMessageBuilder::get_led_brightness(*var_store, *led_id)
+            }
+            Command::SetLedBrightness(var_store, led_id, brightness) => {
+                RazerMessageBuilder::set_led_brightness(*var_store, *led_id, *brightness)
+            }
+        }
+    }
+
+    /// Decode a request report back into the command that produced it.
+    pub(crate) fn decode(request: &RazerMessage) -> Result<Command> {
+        let args = request.arguments();
+        let command = match (request.command_class(), request.command_id()) {
+            (0x00, 0x81) => Command::GetFirmwareVersion,
+            (0x00, 0x82) => Command::GetSerial,
+            (0x00, 0x84) => Command::GetDeviceMode,
+            (0x00, 0x04) => Command::SetDeviceMode(
+                decode_device_mode(args[0])
+                    .ok_or_else(|| invalid_argument("Invalid device mode"))?,
+            ),
+            (0x07, 0x80) => Command::GetBatteryLevel,
+            (0x07, 0x84) => Command::GetChargingStatus,
+            (0x07, 0x83) => Command::GetIdleTime,
+            (0x07, 0x03) => Command::SetIdleTime(decode_u16_from_bytes(&args[0..=1])),
+            (0x07, 0x81) => Command::GetLowBatteryThreshold,
+            (0x07, 0x01) => Command::SetLowBatteryThreshold(args[0]),
+            (0x04, 0x85) => Command::GetDpi(decode_var_store(args[0])?),
+            (0x04, 0x05) => Command::SetDpi(decode_var_store(args[0])?, decode_dpi(&args[1..=4])),
+            (0x04, 0x86) => Command::GetDpiStages(decode_var_store(args[0])?),
+            (0x04, 0x06) => {
+                Command::SetDpiStages(decode_var_store(args[0])?, decode_dpi_stages(args)?)
+            }
+            (0x00, 0x85) => Command::GetPollingRate,
+            (0x00, 0x05) => Command::SetPollingRate(
+                decode_normal_polling_rate(args[0])
+                    .ok_or_else(|| invalid_argument("Invalid polling rate"))?,
+            ),
+            (0x00, 0xC0) => Command::GetPollingRateExtended,
+            (0x00, 0x40) => Command::SetPollingRateExtended(
+                decode_extended_polling_rate(args[1])
+                    .ok_or_else(|| invalid_argument("Invalid extended polling rate"))?,
+            ),
+            (0x0F, 0x02) => Command::SetMatrixEffect(
+                decode_var_store(args[0])?,
+                decode_led_id(args[1])?,
+                decode_matrix_effect(args).ok_or_else(|| invalid_argument("Invalid effect"))?,
+            ),
+            (0x0F, 0x82) => {
+                Command::GetMatrixEffect(decode_var_store(args[0])?, decode_led_id(args[1])?)
+            }
+            (0x0F, 0x84) => {
+                Command::GetLedBrightness(decode_var_store(args[0])?, decode_led_id(args[1])?)
+            }
+            (0x0F, 0x04) => Command::SetLedBrightness(
+                decode_var_store(args[0])?,
+                decode_led_id(args[1])?,
+                args[2],
+            ),
+            _ => return Err(Error::Unsupported),
+        };
+        Ok(command)
+    }
+}
+
+impl Response {
+    /// Decode a response report, based on the command class/id it echoes.
+    pub(crate) fn decode(response: &RazerMessage) -> Result<Response> {
+        let args = response.arguments();
+        let response = match (response.command_class(), response.command_id()) {
+            (0x00, 0x81) => Response::FirmwareVersion(FirmwareVersion {
+                major: args[0],
+                minor: args[1],
+            }),
+            (0x00, 0x82) => Response::Serial(decode_serial(args)?),
+            (0x00, 0x84) => Response::DeviceMode(
+                decode_device_mode(args[0])
+                    .ok_or_else(|| Error::InvalidResponse("Invalid device mode".into()))?,
+            ),
+            (0x07, 0x80) => Response::BatteryLevel(args[1]),
+            (0x07, 0x84) => Response::ChargingStatus(args[1] > 0),
+            (0x07, 0x83) => Response::IdleTime(decode_u16_from_bytes(&args[0..=1])),
+            (0x07, 0x81) => Response::LowBatteryThreshold(args[0]),
+            (0x04, 0x85) => Response::Dpi(decode_dpi(&args[1..=4])),
+            (0x04, 0x86) => Response::DpiStages(
+                decode_dpi_stages(args).map_err(|err| Error::InvalidResponse(err.to_string()))?,
+            ),
+            (0x00, 0x85) => Response::PollingRate(
+                decode_normal_polling_rate(args[0])
+                    .ok_or_else(|| Error::InvalidResponse("Invalid polling rate".into()))?,
+            ),
+            (0x0F, 0x82) => Response::MatrixEffect(
+                decode_matrix_effect(args)
+                    .ok_or_else(|| Error::InvalidResponse("Invalid effect".into()))?,
+            ),
+            (0x0F, 0x84) => Response::LedBrightness(args[2]),
+            (0x00, 0xC0) => {
+                Response::PollingRateExtended(decode_extended_polling_rate(args[1]).ok_or_else(
+                    || Error::InvalidResponse("Invalid extended polling rate".into()),
+                )?)
+            }
+            (0x00, 0x04)
+            | (0x07, 0x03)
+            | (0x07, 0x01)
+            | (0x04, 0x05)
+            | (0x04, 0x06)
+            | (0x00, 0x05)
+            | (0x00, 0x40)
+            | (0x0F, 0x02)
+            | (0x0F, 0x04) => Response::Ack,
+            _ => return Err(Error::Unsupported),
+        };
+        Ok(response)
+    }
+
+    /// Write this response into `message`, which starts out as a copy of the request.
+    pub(crate) fn encode(&self, message: &mut RazerMessage) {
+        let args = message.arguments_mut();
+        match self {
+            Response::FirmwareVersion(version) => {
+                args[0] = version.major;
+                args[1] = version.minor;
+            }
+            Response::Serial(serial) => {
+                let serial = &serial.as_bytes()[..serial.len().min(RAZER_SERIAL_SIZE)];
+                args[..serial.len()].copy_from_slice(serial);
+            }
+            Response::DeviceMode(mode) => args[0] = encode_device_mode(*mode),
+            Response::BatteryLevel(level) => args[1] = *level,
+            Response::ChargingStatus(charging) => args[1] = *charging as u8,
+            Response::IdleTime(seconds) => {
+                args[0..=1].copy_from_slice(&encode_u16_as_bytes(*seconds))
+            }
+            Response::LowBatteryThreshold(threshold) => args[0] = *threshold,
+            Response::Dpi(dpi) => encode_dpi(&mut args[1..=4], *dpi),
+            Response::DpiStages(dpi_stages) => {
+                args[1] = dpi_stages.active_1_idx();
+                args[2] = dpi_stages.stages().len() as u8;
+                args[3..]
+                    .chunks_exact_mut(0x07)
+                    .zip(dpi_stages.stages())
+                    .enumerate()
+                    .for_each(|(i, (chunk, &dpi))| {
+                        // The device numbers stages from 1 in its responses
+                        chunk[0] = i as u8 + 1;
+                        encode_dpi(&mut chunk[1..=4], dpi);
+                    });
+            }
+            Response::PollingRate(polling_rate) => {
+                args[0] = encode_normal_polling_rate(*polling_rate)
+            }
+            Response::PollingRateExtended(polling_rate) => {
+                args[1] = encode_extended_polling_rate(*polling_rate)
+            }
+            Response::MatrixEffect(effect) => {
+                encode_matrix_effect(args, *effect);
+            }
+            Response::LedBrightness(brightness) => args[2] = *brightness,
+            Response::Ack => {}
+        }
+    }
+}
+
+/// Human readable name of a known command class/id pair
+pub(crate) fn command_name(command_class: u8, command_id: u8) -> Option<&'static str> {
+    let name = match (command_class, command_id) {
+        (0x00, 0x81) => "Get firmware version",
+        (0x00, 0x82) => "Get serial",
+        (0x00, 0x84) => "Get device mode",
+        (0x00, 0x04) => "Set device mode",
+        (0x07, 0x80) => "Get battery level",
+        (0x07, 0x84) => "Get charging status",
+        (0x07, 0x83) => "Get idle time",
+        (0x07, 0x03) => "Set idle time",
+        (0x07, 0x81) => "Get low battery threshold",
+        (0x07, 0x01) => "Set low battery threshold",
+        (0x04, 0x85) => "Get DPI",
+        (0x04, 0x05) => "Set DPI",
+        (0x04, 0x86) => "Get DPI stages",
+        (0x04, 0x06) => "Set DPI stages",
+        (0x00, 0x85) => "Get polling rate",
+        (0x00, 0x05) => "Set polling rate",
+        (0x00, 0xC0) => "Get extended polling rate",
+        (0x00, 0x40) => "Set extended polling rate",
+        (0x0F, 0x02) => "Set extended matrix effect",
+        (0x0F, 0x82) => "Get extended matrix effect",
+        (0x0F, 0x84) => "Get LED brightness",
+        (0x0F, 0x04) => "Set LED brightness",
+        _ => return None,
+    };
+    Some(name)
+}
+
+fn invalid_argument(msg: &str) -> Error {
+    Error::InvalidArgument(msg.into())
+}
+
+/// ASCII, padded with NULs
+fn decode_serial(args: &[u8]) -> Result<String> {
+    let serial = &args[..RAZER_SERIAL_SIZE];
+    let len = serial
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(serial.len());
+    let serial = std::str::from_utf8(&serial[..len])
+        .map_err(|_| Error::InvalidResponse("Serial isn't ASCII".into()))?;
+    Ok(serial.trim().to_owned())
+}
+
+fn encode_device_mode(mode: DeviceMode) -> u8 {
+    match mode {
+        DeviceMode::Normal => 0x00,
+        DeviceMode::Driver => 0x03,
+    }
+}
+
+fn decode_device_mode(byte: u8) -> Option<DeviceMode> {
+    match byte {
+        0x00 => Some(DeviceMode::Normal),
+        0x03 => Some(DeviceMode::Driver),
+        _ => None,
+    }
+}
+
+fn decode_var_store(byte: u8) -> Result<VarStoreId> {
+    VarStoreId::try_from(byte).map_err(|_| invalid_argument("Invalid variable storage"))
+}
+
+fn decode_led_id(byte: u8) -> Result<LedId> {
+    LedId::try_from(byte).map_err(|_| invalid_argument("Invalid LED id"))
+}
+
+/// X and Y as big endian u16s
+fn decode_dpi(bytes: &[u8]) -> Dpi {
+    Dpi {
+        x: decode_u16_from_bytes(&bytes[0..=1]),
+        y: decode_u16_from_bytes(&bytes[2..=3]),
+    }
+}
+
+fn encode_dpi(bytes: &mut [u8], dpi: Dpi) {
+    bytes[0..=1].copy_from_slice(&encode_u16_as_bytes(dpi.x));
+    bytes[2..=3].copy_from_slice(&encode_u16_as_bytes(dpi.y));
+}
+
+/// Format (hex):
+/// 01    varstore
+/// 02    active DPI stage (1 indexed)
+/// 04    number of stages = 4
+///
+/// 01    first DPI stage
+/// 03 20 first stage DPI X = 800
+/// 03 20 first stage DPI Y = 800
+/// 00 00 reserved
+///
+/// 02    second DPI stage
+/// 07 08 second stage DPI X = 1800
+/// 07 08 second stage DPI Y = 1800
+/// 00 00 reserved
+///
+/// 03    third DPI stage
+/// ...
+fn decode_dpi_stages(args: &[u8]) -> Result<DpiStages> {
+    let num_stages = args[2] as usize;
+    let stages = args[3..]
+        .chunks_exact(0x07)
+        .take(num_stages)
+        .map(|dpi_stage| decode_dpi(&dpi_stage[1..=4]))
+        .collect();
+
+    // 1-indexed, convert to 0-indexed
+    let active_stage = args[1]
+        .checked_sub(1)
+        .ok_or_else(|| invalid_argument("Active DPI stage must be 1-indexed"))?;
+    DpiStages::new(active_stage, stages)
+}
+
+fn encode_normal_polling_rate(polling_rate: NormalPollingRate) -> u8 {
+    match polling_rate {
+        NormalPollingRate::Rate1000 => 0x01,
+        NormalPollingRate::Rate500 => 0x02,
+        NormalPollingRate::Rate125 => 0x08,
+    }
+}
+
+fn decode_normal_polling_rate(byte: u8) -> Option<NormalPollingRate> {
+    match byte {
+        0x01 => Some(NormalPollingRate::Rate1000),
+        0x02 => Some(NormalPollingRate::Rate500),
+        0x08 => Some(NormalPollingRate::Rate125),
+        _ => None,
+    }
+}
+
+fn encode_extended_polling_rate(polling_rate: ExtendedPollingRate) -> u8 {
+    match polling_rate {
+        ExtendedPollingRate::Rate8000 => 0x01,
+        ExtendedPollingRate::Rate4000 => 0x02,
+        ExtendedPollingRate::Rate2000 => 0x04,
+        ExtendedPollingRate::Rate1000 => 0x08,
+        ExtendedPollingRate::Rate500 => 0x10,
+        ExtendedPollingRate::Rate250 => 0x20,
+        ExtendedPollingRate::Rate125 => 0x40,
+    }
+}
+
+fn decode_extended_polling_rate(byte: u8) -> Option<ExtendedPollingRate> {
+    match byte {
+        0x01 => Some(ExtendedPollingRate::Rate8000),
+        0x02 => Some(ExtendedPollingRate::Rate4000),
+        0x04 => Some(ExtendedPollingRate::Rate2000),
+        0x08 => Some(ExtendedPollingRate::Rate1000),
+        0x10 => Some(ExtendedPollingRate::Rate500),
+        0x20 => Some(ExtendedPollingRate::Rate250),
+        0x40 => Some(ExtendedPollingRate::Rate125),
+        _ => None,
+    }
+}
+
+/// Write the effect id and its parameters after the variable storage and LED id, and return
+/// the data size they need
+fn encode_matrix_effect(args: &mut [u8], effect: ExtendedMatrixEffect) -> u8 {
+    args[2] = effect.into();
+    match effect {
+        ExtendedMatrixEffect::None | ExtendedMatrixEffect::Spectrum => 0x06,
+        ExtendedMatrixEffect::Static(color) => {
+            let payload = [0x01, color.r, color.g, color.b];
+            args[5..=8].copy_from_slice(&payload);
+            0x09
+        }
+        ExtendedMatrixEffect::Breathing(effect) => match effect {
+            BreathingEffect::Single(color) => {
+                let payload = [0x01, 0x00, 0x01, color.r, color.g, color.b];
+                args[3..=8].copy_from_slice(&payload);
+                0x09
+            }
+            BreathingEffect::Dual(color, color1) => {
+                let payload = [
+                    0x02, 0x00, 0x02, color.r, color.g, color.b, color1.r, color1.g, color1.b,
+                ];
+                args[3..=11].copy_from_slice(&payload);
+                0x0C
+            }
+            BreathingEffect::Random => 0x06,
+        },
+        ExtendedMatrixEffect::Wave(direction, speed) => {
+            args[3] = direction as u8;
+            args[4] = clamp(speed, 0x01, 0xFF);
+            0x06
+        }
+        ExtendedMatrixEffect::Reactive(color, speed) => {
+            let speed = clamp(speed, 0x01, 0x04);
+
+            let payload = [speed, 0x01, color.r, color.g, color.b];
+            args[4..=8].copy_from_slice(&payload);
+            0x09
+        }
+        ExtendedMatrixEffect::Starlight(effect, speed) => {
+            args[4] = clamp(speed, 0x01, 0x03);
+            match effect {
+                StarlightEffect::Single(color) => {
+                    let payload = [0x01, color.r, color.g, color.b];
+                    args[5..=8].copy_from_slice(&payload);
+                    0x09
+                }
+                StarlightEffect::Dual(color, color1) => {
+                    let payload = [
+                        0x02, color.r, color.g, color.b, color1.r, color1.g, color1.b,
+                    ];
+                    args[5..=11].copy_from_slice(&payload);
+                    0x0C
+                }
+                StarlightEffect::Random => 0x06,
+            }
+        }
+        ExtendedMatrixEffect::Wheel(direction) => {
+            args[3] = direction as u8;
+            args[4] = 0x28;
+            0x06
+        }
+    }
+}
+
+/// Inverse of `encode_matrix_effect`. Assumes the device answers a GET with the same layout
+/// as the SET payload: OpenRazer has no decoder for 0x0F/0x82 to check against and no captured
+/// response confirms it yet, the simulator only mirrors this assumption.
+fn decode_matrix_effect(args: &[u8]) -> Option<ExtendedMatrixEffect> {
+    let color = |offset: usize| Color {
+        r: args[offset],
+        g: args[offset + 1],
+        b: args[offset + 2],
+    };
+    let effect = match args[2] {
+        0x00 => ExtendedMatrixEffect::None,
+        0x01 => ExtendedMatrixEffect::Static(color(6)),
+        0x02 => match args[5] {
+            0x00 => ExtendedMatrixEffect::Breathing(BreathingEffect::Random),
+            0x01 => ExtendedMatrixEffect::Breathing(BreathingEffect::Single(color(6))),
+            0x02 => ExtendedMatrixEffect::Breathing(BreathingEffect::Dual(color(6), color(9))),
+            _ => return None,
+        },
+        0x03 => ExtendedMatrixEffect::Spectrum,
+        0x04 => ExtendedMatrixEffect::Wave(WaveDirection::try_from(args[3]).ok()?, args[4]),
+        0x05 => ExtendedMatrixEffect::Reactive(color(6), args[4]),
+        0x07 => {
+            let effect = match args[5] {
+                0x00 => StarlightEffect::Random,
+                0x01 => StarlightEffect::Single(color(6)),
+                0x02 => StarlightEffect::Dual(color(6), color(9)),
+                _ => return None,
+            };
+            ExtendedMatrixEffect::Starlight(effect, args[4])
+        }
+        0x0A => ExtendedMatrixEffect::Wheel(WheelDirection::try_from(args[3]).ok()?),
+        _ => return None,
+    };
+    Some(effect)
+}
+
+impl RazerMessageBuilder {
+    pub(crate) fn get_firmware_version() -> Self {
+        Self {
+            data_size: 0x02,
+            command_class: 0x00,
+            command_id: 0x81,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn get_serial() -> Self {
+        Self {
+            data_size: RAZER_SERIAL_SIZE as u8,
+            command_class: 0x00,
+            command_id: 0x82,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn get_device_mode() -> Self {
+        Self {
+            data_size: 0x02,
+            command_class: 0x00,
+            command_id: 0x84,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn set_device_mode(mode: DeviceMode) -> Self {
+        let mut msg = Self {
+            data_size: 0x02,
+            command_class: 0x00,
+            command_id: 0x04,
+            ..Default::default()
+        };
+        msg.arguments[0] = encode_device_mode(mode);
+        msg.arguments[1] = 0x00; // Parameter, unused by these modes
+        msg
+    }
+
+    /// Message to send to the device asking for battery level.
+    pub(crate) fn get_battery_level() -> Self {
+        Self {
+            data_size: 0x02,
+            command_class: 0x07,
+            command_id: 0x80,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn get_charging_status() -> Self {
+        Self {
+            data_size: 0x02,
+            command_class: 0x07,
+            command_id: 0x84,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn get_idle_time() -> Self {
+        Self {
+            data_size: 0x02,
+            command_class: 0x07,
+            command_id: 0x83,
+            ..Default::default()
+        }
+    }
+
+    /// Seconds of inactivity before the mouse goes to sleep
+    pub(crate) fn set_idle_time(seconds: u16) -> Self {
+        let mut msg = Self {
+            data_size: 0x02,
+            command_class: 0x07,
+            command_id: 0x03,
+            ..Default::default()
+        };
+        let seconds = clamp(seconds, RAZER_MIN_IDLE_SECONDS, RAZER_MAX_IDLE_SECONDS);
+        msg.arguments[0..=1].copy_from_slice(&encode_u16_as_bytes(seconds));
+        msg
+    }
+
+    pub(crate) fn get_low_battery_threshold() -> Self {
+        Self {
+            data_size: 0x01,
+            command_class: 0x07,
+            command_id: 0x81,
+            ..Default::default()
+        }
+    }
+
+    /// `threshold` is a raw battery level, 0-255
+    pub(crate) fn set_low_battery_threshold(threshold: u8) -> Self {
+        let mut msg = Self {
+            data_size: 0x01,
+            command_class: 0x07,
+            command_id: 0x01,
+            ..Default::default()
+        };
+        msg.arguments[0] = clamp(
+            threshold,
+            RAZER_MIN_LOW_BATTERY_THRESHOLD_RAW,
+            RAZER_MAX_LOW_BATTERY_THRESHOLD_RAW,
+        );
+        msg
+    }
+
+    pub(crate) fn get_dpi(var_store: VarStoreId) -> Self {
+        let mut msg = Self {
+            data_size: 0x07,
+            command_class: 0x04,
+            command_id: 0x85,
+            ..Default::default()
+        };
+        msg.arguments[0] = var_store as u8;
+        msg
+    }
+
+    pub(crate) fn set_dpi(var_store: VarStoreId, dpi: Dpi) -> Self {
+        let mut msg = Self {
+            data_size: 0x07,
+            command_class: 0x04,
+            command_id: 0x05,
+            ..Default::default()
+        };
+        let dpi_x = clamp(dpi.x, RAZER_MOUSE_MIN_DPI, RAZER_MOUSE_MAX_DPI);
+        let dpi_y = clamp(dpi.y, RAZER_MOUSE_MIN_DPI, RAZER_MOUSE_MAX_DPI);
+
+        msg.arguments[0] = var_store as u8;
+        msg.arguments[1] = ((dpi_x >> 8) & 0x00FF) as u8;
+        msg.arguments[2] = (dpi_x & 0xFF) as u8;
+        msg.arguments[3] = ((dpi_y >> 8) & 0x00FF) as u8;
+        msg.arguments[4] = (dpi_y & 0xFF) as u8;
+        msg.arguments[5] = 0x00;
+        msg.arguments[6] = 0x00;
+        msg
+    }
+
+    pub(crate) fn get_dpi_stages(var_store: VarStoreId) -> Self {
+        let mut msg = Self {
+            data_size: 0x26,
+            command_class: 0x04,
+            command_id: 0x86,
+            ..Default::default()
+        };
+        msg.arguments[0] = var_store as u8;
+        msg
+    }
+
+    pub(crate) fn set_dpi_stages(var_store: VarStoreId, dpi_stages: &DpiStages) -> Self {
+        let mut msg = Self {
+            data_size: 0x26,
+            command_class: 0x04,
+            command_id: 0x06,
+            ..Default::default()
+        };
+
+        msg.arguments[0] = var_store as u8;
+        msg.arguments[1] = dpi_stages.active_1_idx();
+
+        let num_stages = dpi_stages.stages().len();
+        msg.arguments[2] = num_stages as u8;
+
+        // We write for each stage
+        // nn       - stage number
+        // xx xx    - DPI X (u16)
+        // yy yy    - DPI Y (u16)
+        // 00 00    - Reserved
+        msg.arguments[3..]
+            .chunks_exact_mut(0x07)
+            .take(num_stages)
+            .enumerate()
+            .for_each(|(i, chunk)| {
+                let Dpi { x, y } = dpi_stages.stages()[i];
+                chunk[0] = i as u8;
+                chunk[1..=2].copy_from_slice(&encode_u16_as_bytes(x));
+                chunk[3..=4].copy_from_slice(&encode_u16_as_bytes(y));
+                chunk[5] = 0;
+                chunk[6] = 0;
+            });
+        msg
+    }
+
+    pub(crate) fn get_polling_rate() -> Self {
+        Self {
+            data_size: 0x01,
+            command_class: 0x00,
+            command_id: 0x85,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn set_polling_rate(polling_rate: NormalPollingRate) -> Self {
+        let mut msg = Self {
+            data_size: 0x01,
+            command_class: 0x00,
+            command_id: 0x05,
+            ..Default::default()
+        };
+        msg.arguments[0] = encode_normal_polling_rate(polling_rate);
+        msg
+    }
+
+    pub(crate) fn get_polling_rate_extended() -> Self {
+        Self {
+            data_size: 0x02,
+            command_class: 0x00,
+            command_id: 0xC0,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn set_polling_rate_extended(polling_rate: ExtendedPollingRate) -> Self {
+        let mut msg = Self {
+            data_size: 0x02,
+            command_class: 0x00,
+            command_id: 0x40,
+            ..Default::default()
+        };
+        msg.arguments[0] = 0x00; // https://github.com/openrazer/openrazer/blob/16d13ef025d7cd32f03a6acc0548f9316e42b35a/driver/razermouse_driver.c#L1681
+        msg.arguments[1] = encode_extended_polling_rate(polling_rate);
+        msg
+    }
+
+    pub(crate) fn get_led_brightness(var_store: VarStoreId, led_id: LedId) -> Self {
+        let mut msg = Self {
+            data_size: 0x03,
+            command_class: 0x0F,
+            command_id: 0x84,
+            ..Default::default()
+        };
+        msg.arguments[0] = var_store as u8;
+        msg.arguments[1] = led_id as u8;
+        msg
+    }
+
+    pub(crate) fn set_led_brightness(var_store: VarStoreId, led_id: LedId, brightness: u8) -> Self {
+        let mut msg = Self {
+            data_size: 0x03,
+            command_class: 0x0F,
+            command_id: 0x04,
+            ..Default::default()
+        };
+        msg.arguments[0] = var_store as u8;
+        msg.arguments[1] = led_id as u8;
+        msg.arguments[2] = brightness;
+        msg
+    }
+
+    pub(crate) fn chroma_extended_matrix_effect(
+        var_store: VarStoreId,
+        led_id: LedId,
+        effect: ExtendedMatrixEffect,
+    ) -> Self {
+        let mut msg = Self {
+            command_class: 0x0F,
+            command_id: 0x02,
+            ..Default::default()
+        };
+        msg.arguments[0] = var_store as u8;
+        msg.arguments[1] = led_id as u8;
+        msg.data_size = encode_matrix_effect(&mut msg.arguments, effect);
+        msg
+    }
+
+    /// The response is decoded by `decode_matrix_effect`, on an unconfirmed layout
+    pub(crate) fn get_extended_matrix_effect(var_store: VarStoreId, led_id: LedId) -> Self {
+        let mut msg = Self {
+            data_size: 0x0C,
+            command_class: 0x0F,
+            command_id: 0x82,
+            ..Default::default()
+        };
+        msg.arguments[0] = var_store as u8;
+        msg.arguments[1] = led_id as u8;
+        msg
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zerocopy::IntoBytes;
+
+    use super::*;
+    use crate::common::RAZER_REPORT_SIZE;
+
+    fn response(command_class: u8, command_id: u8, args: &[u8]) -> RazerMessage {
+        let mut msg = RazerMessageBuilder {
+            command_class,
+            command_id,
+            ..Default::default()
+        };
+        msg.arguments[..args.len()].copy_from_slice(args);
+        msg.build()
+    }
+
+    #[test]
+    fn encode_get_dpi_fixture() {
+        let request = Command::GetDpi(VarStoreId::NoStore)
+            .encode()
+            .with_transaction_id(0x3f)
+            .build();
+
+        let mut expected = [0u8; RAZER_REPORT_SIZE];
+        expected[..8].copy_from_slice(&[0x00, 0x3f, 0x00, 0x00, 0x00, 0x07, 0x04, 0x85]);
+        expected[RAZER_REPORT_SIZE - 2] = 0x07 ^ 0x04 ^ 0x85;
+        assert_eq!(request.as_bytes(), expected);
+    }
+
+    #[test]
+    fn decode_dpi_stages_fixture() {
+        #[rustfmt::skip]
+        let args = [
+            0x01, 0x02, 0x04,
+            0x01, 0x03, 0x20, 0x03, 0x20, 0x00, 0x00,
+            0x02, 0x07, 0x08, 0x07, 0x08, 0x00, 0x00,
+            0x03, 0x0C, 0x80, 0x06, 0x40, 0x00, 0x00,
+            0x04, 0x19, 0x00, 0x19, 0x00, 0x00, 0x00,
+        ];
+        let stages = vec![800.into(), 1800.into(), (3200, 1600).into(), 6400.into()];
+
+        assert_eq!(
+            Response::decode(&response(0x04, 0x86, &args)).unwrap(),
+            Response::DpiStages(DpiStages::new(1, stages).unwrap())
+        );
+    }
+
+    #[test]
+    fn decode_invalid_responses() {
+        assert!(matches!(
+            Response::decode(&response(0x00, 0x85, &[0x03])),
+            Err(Error::InvalidResponse(_))
+        ));
+        assert!(matches!(
+            Response::decode(&response(0x04, 0x86, &[0x01, 0x00, 0x01])),
+            Err(Error::InvalidResponse(_))
+        ));
+        assert!(matches!(
+            Response::decode(&response(0x3F, 0x80, &[])),
+            Err(Error::Unsupported)
+        ));
+    }
+
+    #[test]
+    fn commands_round_trip() {
+        let color = Color {
+            r: 0x0c,
+            g: 0xff,
+            b: 0x1d,
+        };
+        let color1 = Color {
+            r: 0x80,
+            g: 0x00,
+            b: 0xff,
+        };
+        let dpi_stages = DpiStages::new(2, vec![400.into(), 800.into(), (1600, 800).into()]);
+        let commands = [
+            Command::GetFirmwareVersion,
+            Command::GetSerial,
+            Command::GetDeviceMode,
+            Command::SetDeviceMode(DeviceMode::Driver),
+            Command::GetBatteryLevel,
+            Command::GetChargingStatus,
+            Command::GetIdleTime,
+            Command::SetIdleTime(600),
+            Command::GetLowBatteryThreshold,
+            Command::SetLowBatteryThreshold(0x26),
+            Command::GetDpi(VarStoreId::NoStore),
+            Command::SetDpi(VarStoreId::NoStore, (1600, 800).into()),
+            Command::GetDpiStages(VarStoreId::VarStore),
+            Command::SetDpiStages(VarStoreId::VarStore, dpi_stages.unwrap()),
+            Command::GetPollingRate,
+            Command::SetPollingRate(NormalPollingRate::Rate500),
+            Command::GetPollingRateExtended,
+            Command::SetPollingRateExtended(ExtendedPollingRate::Rate4000),
+            Command::SetMatrixEffect(
+                VarStoreId::VarStore,
+                LedId::Logo,
+                ExtendedMatrixEffect::Static(color),
+            ),
+            Command::SetMatrixEffect(
+                VarStoreId::VarStore,
+                LedId::Logo,
+                ExtendedMatrixEffect::Breathing(BreathingEffect::Dual(color, color)),
+            ),
+            Command::SetMatrixEffect(
+                VarStoreId::VarStore,
+                LedId::Logo,
+                ExtendedMatrixEffect::Reactive(color, 0x02),
+            ),
+            Command::SetMatrixEffect(
+                VarStoreId::NoStore,
+                LedId::ScrollWheel,
+                ExtendedMatrixEffect::Wave(WaveDirection::Right, 0x28),
+            ),
+            Command::SetMatrixEffect(
+                VarStoreId::VarStore,
+                LedId::Backlight,
+                ExtendedMatrixEffect::Starlight(StarlightEffect::Dual(color, color1), 0x03),
+            ),
+            Command::SetMatrixEffect(
+                VarStoreId::VarStore,
+                LedId::Logo,
+                ExtendedMatrixEffect::Wheel(WheelDirection::CounterClockwise),
+            ),
+            Command::GetMatrixEffect(VarStoreId::VarStore, LedId::Logo),
+            Command::GetMatrixEffect(VarStoreId::NoStore, LedId::LeftSide),
+            Command::GetLedBrightness(VarStoreId::VarStore, LedId::Logo),
+            Command::SetLedBrightness(VarStoreId::NoStore, LedId::Logo, 0x80),
+        ];
+
+        for command in commands {
+            let request = command.encode().build();
+            assert_eq!(Command::decode(&request).unwrap(), command);
+        }
+    }
+
+    #[test]
+    fn responses_round_trip() {
+        let dpi_stages = DpiStages::new(0, vec![500.into(), (1000, 2000).into()]).unwrap();
+        let color = Color {
+            r: 0x12,
+            g: 0x34,
+            b: 0x56,
+        };
+        let responses = [
+            (
+                Command::GetFirmwareVersion,
+                Response::FirmwareVersion(FirmwareVersion { major: 1, minor: 4 }),
+            ),
+            (
+                Command::GetSerial,
+                Response::Serial("PM2049H08311234".into()),
+            ),
+            (
+                Command::GetDeviceMode,
+                Response::DeviceMode(DeviceMode::Driver),
+            ),
+            (Command::GetBatteryLevel, Response::BatteryLevel(0xB3)),
+            (Command::GetChargingStatus, Response::ChargingStatus(true)),
+            (Command::GetIdleTime, Response::IdleTime(300)),
+            (
+                Command::GetLowBatteryThreshold,
+                Response::LowBatteryThreshold(0x0C),
+            ),
+            (Command::SetIdleTime(900), Response::Ack),
+            (
+                Command::GetDpi(VarStoreId::NoStore),
+                Response::Dpi((3200, 1600).into()),
+            ),
+            (
+                Command::GetDpiStages(VarStoreId::VarStore),
+                Response::DpiStages(dpi_stages),
+            ),
+            (
+                Command::GetPollingRate,
+                Response::PollingRate(NormalPollingRate::Rate125),
+            ),
+            (
+                Command::GetPollingRateExtended,
+                Response::PollingRateExtended(ExtendedPollingRate::Rate8000),
+            ),
+            (
+                Command::GetLedBrightness(VarStoreId::VarStore, LedId::Logo),
+                Response::LedBrightness(0x40),
+            ),
+            (
+                Command::GetMatrixEffect(VarStoreId::VarStore, LedId::Logo),
+                Response::MatrixEffect(ExtendedMatrixEffect::Static(color)),
+            ),
+            (
+                Command::GetMatrixEffect(VarStoreId::VarStore, LedId::Logo),
+                Response::MatrixEffect(ExtendedMatrixEffect::Breathing(BreathingEffect::Dual(
+                    color, color,
+                ))),
+            ),
+            (
+                Command::GetMatrixEffect(VarStoreId::VarStore, LedId::Logo),
+                Response::MatrixEffect(ExtendedMatrixEffect::Reactive(color, 0x03)),
+            ),
+            (
+                Command::GetMatrixEffect(VarStoreId::VarStore, LedId::Logo),
+                Response::MatrixEffect(ExtendedMatrixEffect::Breathing(BreathingEffect::Random)),
+            ),
+            (
+                Command::GetMatrixEffect(VarStoreId::VarStore, LedId::ScrollWheel),
+                Response::MatrixEffect(ExtendedMatrixEffect::Wave(WaveDirection::Left, 0x10)),
+            ),
+            (
+                Command::GetMatrixEffect(VarStoreId::VarStore, LedId::Logo),
+                Response::MatrixEffect(ExtendedMatrixEffect::Starlight(
+                    StarlightEffect::Single(color),
+                    0x01,
+                )),
+            ),
+            (
+                Command::GetMatrixEffect(VarStoreId::VarStore, LedId::Logo),
+                Response::MatrixEffect(ExtendedMatrixEffect::Starlight(
+                    StarlightEffect::Random,
+                    0x02,
+                )),
+            ),
+            (
+                Command::GetMatrixEffect(VarStoreId::VarStore, LedId::Logo),
+                Response::MatrixEffect(ExtendedMatrixEffect::Wheel(WheelDirection::Clockwise)),
+            ),
+            (
+                Command::SetPollingRate(NormalPollingRate::Rate125),
+                Response::Ack,
+            ),
+        ];
+
+        for (command, response) in responses {
+            let mut message = command.encode().build();
+            response.encode(&mut message);
+            assert_eq!(Response::decode(&message).unwrap(), response);
+        }
+    }
+}
@@ -0,0 +1,312 @@
+use std::{collections::VecDeque, time::Duration};
+
+use nusb::transfer::TransferError;
+use tokio::{
+    sync::{broadcast, mpsc, oneshot},
+    time::Instant,
+};
+
+use crate::{
+    common::{send_command, RAZER_COMMAND_GAP},
+    devices::DeviceEvent,
+    protocol::{Command, Response},
+    transport::RazerTransport,
+    Error, Result,
+};
+
+/// Opens the device again after its transport died, e.g. by finding it again on the bus
+pub(crate) type Reconnect = Box<dyn FnMut() -> Result<Box<dyn RazerTransport>> + Send + Sync>;
+
+/// Handle to a task that owns a device's transport and runs its commands one at a time.
+///
+/// A request and the polling of its response can't interleave with another command's, which
+/// the device can't tell apart. Commands are spaced at least `RAZER_COMMAND_GAP` apart, and a
+/// queued write that a later queued write to the same setting overrides is never sent.
+///
+/// Each command fails with `Error::TransferTimeout` if the device doesn't answer in time.
+/// Once the task started a command it always runs to completion or timeout, so dropping the
+/// future waiting on it can't leave the device halfway through an exchange, and commands
+/// dropped before they started are skipped.
+///
+/// With a `Reconnect`, a command failing in a way that means the transport is dead (the
+/// device was unplugged or stalled) reopens the device and is retried once. A timeout isn't
+/// one: an asleep wireless mouse stays behind the same receiver, reopening it wouldn't help.
+#[derive(Clone)]
+pub(crate) struct CommandQueue {
+    sender: mpsc::UnboundedSender<QueuedRequest>,
+    events: broadcast::Sender<DeviceEvent>,
+    timeout: Duration,
+}
+
+enum QueuedRequest {
+    Command {
+        transaction_id: u8,
+        command: Command,
+        timeout: Duration,
+        reply: oneshot::Sender<Result<Response>>,
+    },
+    Release(oneshot::Sender<Result<()>>),
+}
+
+impl CommandQueue {
+    /// Spawn the task owning `transport`. Must be called within a Tokio runtime.
+    pub(crate) fn new(
+        transport: Box<dyn RazerTransport>,
+        reconnect: Option<Reconnect>,
+        timeout: Duration,
+    ) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let (events, _) = broadcast::channel(8);
+        let connection = Connection {
+            transport: Some(transport),
+            reconnect,
+            events: events.clone(),
+        };
+        tokio::spawn(run(connection, receiver));
+        CommandQueue {
+            sender,
+            events,
+            timeout,
+        }
+    }
+
+    /// A handle to the same queue whose commands time out after `timeout`
+    pub(crate) fn with_timeout(&self, timeout: Duration) -> Self {
+        CommandQueue {
+            timeout,
+            ..self.clone()
+        }
+    }
+
+    pub(crate) fn events(&self) -> broadcast::Receiver<DeviceEvent> {
+        self.events.subscribe()
+    }
+
+    pub(crate) async fn send(&self, transaction_id: u8, command: Command) -> Result<Response> {
+        let (reply, response) = oneshot::channel();
+        self.request(QueuedRequest::Command {
+            transaction_id,
+            command,
+            timeout: self.timeout,
+            reply,
+        })?;
+        response.await.map_err(|_| queue_stopped())?
+    }
+
+    /// Run the commands queued so far, then release the transport and stop. Commands sent
+    /// through clones of this handle afterwards fail.
+    pub(crate) async fn release(self) -> Result<()> {
+        let (reply, result) = oneshot::channel();
+        self.request(QueuedRequest::Release(reply))?;
+        result.await.map_err(|_| queue_stopped())?
+    }
+
+    fn request(&self, request: QueuedRequest) -> Result<()> {
+        self.sender.send(request).map_err(|_| queue_stopped())
+    }
+}
+
+fn queue_stopped() -> Error {
+    Error::Usb(std::io::ErrorKind::BrokenPipe.into())
+}
+
+struct Connection {
+    /// Gone after the device died and couldn't be reopened yet
+    transport: Option<Box<dyn RazerTransport>>,
+    reconnect: Option<Reconnect>,
+    events: broadcast::Sender<DeviceEvent>,
+}
+
+impl Connection {
+    async fn send(
+        &mut self,
+        transaction_id: u8,
+        command: Command,
+        timeout: Duration,
+    ) -> Result<Response> {
+        let result = self
+            .try_send(transaction_id, command.clone(), timeout)
+            .await;
+        match result {
+            Err(err) if is_dead_transport(&err) && self.reconnect.is_some() => {
+                if self.reconnect().await.is_err() {
+                    return Err(err);
+                }
+                self.try_send(transaction_id, command, timeout).await
+            }
+            result => result,
+        }
+    }
+
+    async fn try_send(
+        &self,
+        transaction_id: u8,
+        command: Command,
+        timeout: Duration,
+    ) -> Result<Response> {
+        let Some(transport) = &self.transport else {
+            return Err(Error::Disconnected);
+        };
+        tokio::time::timeout(timeout, send_command(&**transport, transaction_id, command))
+            .await
+            .unwrap_or(Err(Error::TransferTimeout(timeout)))
+    }
+
+    async fn reconnect(&mut self) -> Result<()> {
+        let old_transport = self.transport.take();
+        let mut reconnect = self.reconnect.take().expect("Only called with a reconnect");
+        // Releasing, enumerating and opening devices blocks
+        let (reconnect, transport) = tokio::task::spawn_blocking(move || {
+            // Release the old handle first, the interface can't be claimed twice
+            drop(old_transport);
+            let transport = reconnect();
+            (reconnect, transport)
+        })
+        .await
+        .map_err(|err| Error::Usb(std::io::Error::other(err)))?;
+        self.reconnect = Some(reconnect);
+        self.transport = Some(transport?);
+        let _ = self.events.send(DeviceEvent::Reconnected);
+        Ok(())
+    }
+
+    fn release(self) -> Result<()> {
+        self.transport
+            .map_or(Ok(()), |transport| transport.release())
+    }
+}
+
+/// Whether an error means the transport won't work again, rather than the device
+/// rejecting or garbling one command
+fn is_dead_transport(err: &Error) -> bool {
+    match err {
+        Error::Disconnected | Error::Usb(_) => true,
+        Error::Transfer(err) => *err != TransferError::Cancelled,
+        _ => false,
+    }
+}
+
+async fn run(mut connection: Connection, mut receiver: mpsc::UnboundedReceiver<QueuedRequest>) {
+    let mut queued = VecDeque::new();
+    let mut last_command: Option<Instant> = None;
+
+    loop {
+        if queued.is_empty() {
+            match receiver.recv().await {
+                Some(request) => queued.push_back(request),
+                // Every handle is gone, dropping the connection releases it
+                None => return,
+            }
+        }
+        while let Ok(request) = receiver.try_recv() {
+            queued.push_back(request);
+        }
+
+        match queued.pop_front().expect("Queue was just filled") {
+            QueuedRequest::Command {
+                transaction_id,
+                command,
+                timeout,
+                reply,
+            } => {
+                if reply.is_closed() {
+                    // Cancelled by the caller before it started
+                    continue;
+                }
+                if is_overridden(transaction_id, &command, &queued) {
+                    // Its value would never be observable, so it counts as applied
+                    let _ = reply.send(Ok(Response::Ack));
+                    continue;
+                }
+
+                if let Some(last_command) = last_command {
+                    tokio::time::sleep_until(last_command + RAZER_COMMAND_GAP).await;
+                }
+                let response = connection.send(transaction_id, command, timeout).await;
+                last_command = Some(Instant::now());
+                let _ = reply.send(response);
+            }
+            QueuedRequest::Release(reply) => {
+                let _ = reply.send(connection.release());
+                return;
+            }
+        }
+    }
+}
+
+/// Whether a write is followed by another write to the same setting, with only writes to
+/// other settings queued in between that can't observe it. Cancelled commands are skipped
+/// once they come up, so they neither override nor observe anything.
+fn is_overridden(transaction_id: u8, command: &Command, queued: &VecDeque<QueuedRequest>) -> bool {
+    if !is_write(command) {
+        return false;
+    }
+    for request in queued {
+        match request {
+            QueuedRequest::Command { reply, .. } if reply.is_closed() => continue,
+            QueuedRequest::Command {
+                transaction_id: later_transaction_id,
+                command: later,
+                ..
+            } if is_write(later) => {
+                if *later_transaction_id == transaction_id && writes_same_setting(command, later) {
+                    return true;
+                }
+            }
+            _ => return false,
+        }
+    }
+    false
+}
+
+fn is_write(command: &Command) -> bool {
+    match command {
+        Command::SetDeviceMode(..)
+        | Command::SetIdleTime(..)
+        | Command::SetLowBatteryThreshold(..)
+        | Command::SetDpi(..)
+        | Command::SetDpiStages(..)
+        | Command::SetPollingRate(..)
+        | Command::SetPollingRateExtended(..)
+        | Command::SetMatrixEffect(..)
+        | Command::SetLedBrightness(..) => true,
+        Command::GetFirmwareVersion
+        | Command::GetSerial
+        | Command::GetDeviceMode
+        | Command::GetBatteryLevel
+        | Command::GetChargingStatus
+        | Command::GetIdleTime
+        | Command::GetLowBatteryThreshold
+        | Command::GetDpi(..)
+        | Command::GetDpiStages(..)
+        | Command::GetPollingRate
+        | Command::GetPollingRateExtended
+        | Command::GetMatrixEffect(..)
+        | Command::GetLedBrightness(..) => false,
+    }
+}
+
+fn writes_same_setting(command: &Command, later: &Command) -> bool {
+    match (command, later) {
+        (Command::SetDeviceMode(_), Command::SetDeviceMode(_)) => true,
+        (Command::SetIdleTime(_), Command::SetIdleTime(_)) => true,
+        (Command::SetLowBatteryThreshold(_), Command::SetLowBatteryThreshold(_)) => true,
+        (Command::SetDpi(var_store, _), Command::SetDpi(later_var_store, _)) => {
+            var_store == later_var_store
+        }
+        (Command::SetDpiStages(var_store, _), Command::SetDpiStages(later_var_store, _)) => {
+            var_store == later_var_store
+        }
+        (Command::SetPollingRate(_), Command::SetPollingRate(_)) => true,
+        (Command::SetPollingRateExtended(_), Command::SetPollingRateExtended(_)) => true,
+        (
+            Command::SetMatrixEffect(var_store, led, _),
+            Command::SetMatrixEffect(later_var_store, later_led, _),
+        ) => var_store == later_var_store && led == later_led,
+        (
+            Command::SetLedBrightness(var_store, led, _),
+            Command::SetLedBrightness(later_var_store, later_led, _),
+        ) => var_store == later_var_store && led == later_led,
+        _ => false,
+    }
+}
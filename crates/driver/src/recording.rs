@@ -0,0 +1,156 @@
+use std::{
+    collections::VecDeque,
+    io::{BufRead, Write},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::{dissector::parse_hex, transport::RazerTransport, Error, Result};
+
+/// Which way a recorded report went, seen from the driver.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordedDirection {
+    /// Sent to the device (SET_REPORT)
+    Out,
+    /// Read back from the device (GET_REPORT)
+    In,
+}
+
+/// One line of a recording.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct RecordedReport {
+    /// Milliseconds since the Unix epoch
+    pub timestamp_ms: u64,
+    pub direction: RecordedDirection,
+    /// The report as a hex string
+    pub report: String,
+}
+
+impl RecordedReport {
+    fn new(direction: RecordedDirection, report: &[u8]) -> Self {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64);
+        RecordedReport {
+            timestamp_ms,
+            direction,
+            report: report.iter().map(|byte| format!("{:02x}", byte)).collect(),
+        }
+    }
+}
+
+/// Passes reports through to another transport and logs each successful transfer as a JSONL line.
+///
+/// The log can be served back by a `ReplayTransport`.
+pub struct RecordingTransport {
+    transport: Box<dyn RazerTransport>,
+    log: Mutex<Box<dyn Write + Send>>,
+}
+
+impl RecordingTransport {
+    pub fn new(transport: Box<dyn RazerTransport>, log: Box<dyn Write + Send>) -> Self {
+        RecordingTransport {
+            transport,
+            log: Mutex::new(log),
+        }
+    }
+
+    fn record(&self, direction: RecordedDirection, report: &[u8]) -> Result<()> {
+        let line = serde_json::to_string(&RecordedReport::new(direction, report))
+            .expect("Recorded reports always serialize");
+        let mut log = self.log.lock().unwrap();
+        writeln!(log, "{}", line)?;
+        log.flush()?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RazerTransport for RecordingTransport {
+    async fn send_report(&self, report: &[u8]) -> Result<()> {
+        self.transport.send_report(report).await?;
+        self.record(RecordedDirection::Out, report)
+    }
+
+    async fn receive_report(&self) -> Result<Vec<u8>> {
+        let report = self.transport.receive_report().await?;
+        self.record(RecordedDirection::In, &report)?;
+        Ok(report)
+    }
+
+    fn release(self: Box<Self>) -> Result<()> {
+        self.transport.release()
+    }
+}
+
+/// Serves the responses of a recording back in order, in place of the device.
+///
+/// Each report sent must match the next one recorded, so a replay fails as soon as the
+/// driver's traffic diverges from the recording.
+#[derive(Debug)]
+pub struct ReplayTransport(Mutex<VecDeque<(RecordedDirection, Vec<u8>)>>);
+
+impl ReplayTransport {
+    pub fn new(reports: Vec<RecordedReport>) -> Result<Self> {
+        let reports = reports
+            .into_iter()
+            .map(|recorded| Ok((recorded.direction, parse_hex(&recorded.report)?)))
+            .collect::<Result<_>>()?;
+        Ok(ReplayTransport(Mutex::new(reports)))
+    }
+
+    /// Read a recording written by a `RecordingTransport`.
+    pub fn from_jsonl(reader: impl BufRead) -> Result<Self> {
+        let mut reports = Vec::new();
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let report = serde_json::from_str(&line).map_err(|err| {
+                Error::InvalidArgument(format!("Recording line {}: {}", index + 1, err))
+            })?;
+            reports.push(report);
+        }
+        Self::new(reports)
+    }
+
+    /// Whether every recorded report was replayed
+    pub fn is_finished(&self) -> bool {
+        self.0.lock().unwrap().is_empty()
+    }
+
+    fn next(&self, direction: RecordedDirection) -> Result<Vec<u8>> {
+        let mut reports = self.0.lock().unwrap();
+        match reports.pop_front() {
+            Some((recorded_direction, report)) if recorded_direction == direction => Ok(report),
+            Some((recorded_direction, _)) => Err(Error::InvalidArgument(format!(
+                "Replay expected a {:?} report next but got {:?}",
+                recorded_direction, direction
+            ))),
+            None => Err(Error::InvalidArgument("Replay ran out of reports".into())),
+        }
+    }
+}
+
+#[async_trait]
+impl RazerTransport for ReplayTransport {
+    async fn send_report(&self, report: &[u8]) -> Result<()> {
+        let recorded = self.next(RecordedDirection::Out)?;
+        if recorded != report {
+            return Err(Error::InvalidArgument(format!(
+                "Replay expected report {:02x?} but got {:02x?}",
+                recorded, report
+            )));
+        }
+        Ok(())
+    }
+
+    async fn receive_report(&self) -> Result<Vec<u8>> {
+        self.next(RecordedDirection::In)
+    }
+}
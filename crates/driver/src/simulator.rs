@@ -0,0 +1,331 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use nusb::transfer::TransferError;
+use zerocopy::{FromBytes, IntoBytes};
+
+use crate::{
+    chroma::{ExtendedMatrixEffect, LedId},
+    common::{
+        DeviceMode, Dpi, DpiStages, FirmwareVersion, NormalPollingRate, PollingRate, RazerMessage,
+        RazerStatus, VarStoreId, RAZER_REPORT_SIZE,
+    },
+    protocol::{Command, Response},
+    transport::RazerTransport,
+    Error, Result,
+};
+
+/// Device state of a `SimulatedMouse`, as it would be reported over the wire.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimulatedState {
+    pub firmware_version: FirmwareVersion,
+    pub serial: String,
+    pub device_mode: DeviceMode,
+    /// The DPI in use
+    pub dpi: Dpi,
+    /// The DPI saved in onboard memory, which the mouse starts with
+    pub onboard_dpi: Dpi,
+    /// The DPI stages in use
+    pub dpi_stages: DpiStages,
+    /// The DPI stages saved in onboard memory
+    pub onboard_dpi_stages: DpiStages,
+    /// Only read and written by the commands of its family, like on a real device
+    pub polling_rate: PollingRate,
+    /// Raw battery level, 0-255
+    pub battery_level: u8,
+    pub charging: bool,
+    /// Seconds before going to sleep
+    pub idle_time: u16,
+    /// Raw battery level, 0-255
+    pub low_battery_threshold: u8,
+    /// Effect of each LED the mouse has
+    pub led_effects: BTreeMap<LedId, ExtendedMatrixEffect>,
+    /// Effect of each LED saved in onboard memory
+    pub onboard_led_effects: BTreeMap<LedId, ExtendedMatrixEffect>,
+    /// Brightness of each LED the mouse has, 0-255
+    pub led_brightness: BTreeMap<LedId, u8>,
+    /// Brightness of each LED saved in onboard memory
+    pub onboard_led_brightness: BTreeMap<LedId, u8>,
+}
+
+impl Default for SimulatedState {
+    /// Factory settings of a DeathAdder V2 Pro
+    fn default() -> Self {
+        let stages = [400, 800, 1600, 3200, 6400].map(Dpi::from).to_vec();
+        let dpi_stages = DpiStages::new(1, stages).expect("Default DPI stages are valid");
+        let led_effects = BTreeMap::from([(LedId::Logo, ExtendedMatrixEffect::Spectrum)]);
+        let led_brightness = BTreeMap::from([(LedId::Logo, 0xFF)]);
+        Self {
+            firmware_version: FirmwareVersion { major: 1, minor: 4 },
+            serial: "PM2049H08310000".into(),
+            device_mode: DeviceMode::Normal,
+            dpi: 800.into(),
+            onboard_dpi: 800.into(),
+            dpi_stages: dpi_stages.clone(),
+            onboard_dpi_stages: dpi_stages,
+            polling_rate: NormalPollingRate::Rate1000.into(),
+            battery_level: 0xFF,
+            charging: false,
+            idle_time: 300,
+            low_battery_threshold: 0x26,
+            led_effects: led_effects.clone(),
+            onboard_led_effects: led_effects,
+            led_brightness: led_brightness.clone(),
+            onboard_led_brightness: led_brightness,
+        }
+    }
+}
+
+/// Misbehaviour seen on real hardware that a `SimulatedMouse` can reproduce on demand.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SimulatedFault {
+    /// The next DPI read reports 0x0, like a mouse that is queried too quickly.
+    ZeroDpi,
+    /// The next response is read back with a busy status before the real one is ready.
+    Busy,
+    /// The next report sent to the device stalls.
+    Stall,
+    /// The next response read back is still the one to the previous request.
+    StaleResponse,
+    /// The next response read back has a bad CRC.
+    CorruptCrc,
+    /// The next report sent to the device never completes, like an asleep wireless mouse.
+    Hang,
+    /// The next report sent to the device fails like the mouse was unplugged.
+    Disconnect,
+}
+
+/// An in-process emulation of a DeathAdder V2 Pro that answers Razer reports from its own state.
+///
+/// Clones share the same device, so a test can keep a handle to inspect the state or inject
+/// faults while a `RazerDeviceClaimed` drives another clone.
+#[derive(Clone, Debug, Default)]
+pub struct SimulatedMouse(Arc<Mutex<SimulatedMouseInner>>);
+
+#[derive(Debug, Default)]
+struct SimulatedMouseInner {
+    state: SimulatedState,
+    response: Option<RazerMessage>,
+    previous_response: Option<RazerMessage>,
+    faults: Vec<SimulatedFault>,
+}
+
+impl SimulatedMouse {
+    pub fn new(state: SimulatedState) -> Self {
+        SimulatedMouse(Arc::new(Mutex::new(SimulatedMouseInner {
+            state,
+            ..Default::default()
+        })))
+    }
+
+    /// A snapshot of the current device state
+    pub fn state(&self) -> SimulatedState {
+        self.0.lock().unwrap().state.clone()
+    }
+
+    pub fn set_battery(&self, battery_level: u8, charging: bool) {
+        let mut inner = self.0.lock().unwrap();
+        inner.state.battery_level = battery_level;
+        inner.state.charging = charging;
+    }
+
+    /// Queue a fault. Each injected fault triggers once, at the first transfer it applies to.
+    pub fn inject_fault(&self, fault: SimulatedFault) {
+        self.0.lock().unwrap().faults.push(fault);
+    }
+}
+
+impl SimulatedMouseInner {
+    /// Remove one pending `fault`, returning whether there was one.
+    fn take_fault(&mut self, fault: SimulatedFault) -> bool {
+        match self.faults.iter().position(|&pending| pending == fault) {
+            Some(index) => {
+                self.faults.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Apply a request to the device state and build the matching response.
+    fn handle(&mut self, mut message: RazerMessage) -> RazerMessage {
+        let status = match Command::decode(&message).and_then(|command| self.execute(command)) {
+            Ok(response) => {
+                response.encode(&mut message);
+                RazerStatus::Success
+            }
+            Err(Error::Unsupported) => RazerStatus::NotSupported,
+            Err(_) => RazerStatus::Failure,
+        };
+
+        message.set_status(status);
+        message.update_crc();
+        message
+    }
+
+    fn execute(&mut self, command: Command) -> Result<Response> {
+        let response = match command {
+            Command::GetFirmwareVersion => Response::FirmwareVersion(self.state.firmware_version),
+            Command::GetSerial => Response::Serial(self.state.serial.clone()),
+            Command::GetDeviceMode => Response::DeviceMode(self.state.device_mode),
+            Command::SetDeviceMode(mode) => {
+                self.state.device_mode = mode;
+                Response::Ack
+            }
+            Command::GetBatteryLevel => Response::BatteryLevel(self.state.battery_level),
+            Command::GetChargingStatus => Response::ChargingStatus(self.state.charging),
+            Command::GetIdleTime => Response::IdleTime(self.state.idle_time),
+            Command::SetIdleTime(seconds) => {
+                self.state.idle_time = seconds;
+                Response::Ack
+            }
+            Command::GetLowBatteryThreshold => {
+                Response::LowBatteryThreshold(self.state.low_battery_threshold)
+            }
+            Command::SetLowBatteryThreshold(threshold) => {
+                self.state.low_battery_threshold = threshold;
+                Response::Ack
+            }
+            Command::GetDpi(var_store) => {
+                if self.take_fault(SimulatedFault::ZeroDpi) {
+                    Response::Dpi(Dpi { x: 0, y: 0 })
+                } else if var_store == VarStoreId::VarStore {
+                    Response::Dpi(self.state.onboard_dpi)
+                } else {
+                    Response::Dpi(self.state.dpi)
+                }
+            }
+            Command::SetDpi(var_store, dpi) => {
+                // Saving a DPI also switches to it
+                if var_store == VarStoreId::VarStore {
+                    self.state.onboard_dpi = dpi;
+                }
+                self.state.dpi = dpi;
+                Response::Ack
+            }
+            Command::GetDpiStages(var_store) => {
+                if var_store == VarStoreId::VarStore {
+                    Response::DpiStages(self.state.onboard_dpi_stages.clone())
+                } else {
+                    Response::DpiStages(self.state.dpi_stages.clone())
+                }
+            }
+            Command::SetDpiStages(var_store, dpi_stages) => {
+                // Like the DPI, saving them also switches to them
+                if var_store == VarStoreId::VarStore {
+                    self.state.onboard_dpi_stages = dpi_stages.clone();
+                }
+                self.state.dpi_stages = dpi_stages;
+                Response::Ack
+            }
+            Command::GetPollingRate => match self.state.polling_rate {
+                PollingRate::Normal(polling_rate) => Response::PollingRate(polling_rate),
+                PollingRate::Extended(_) => return Err(Error::Unsupported),
+            },
+            Command::SetPollingRate(polling_rate) => match self.state.polling_rate {
+                PollingRate::Normal(_) => {
+                    self.state.polling_rate = polling_rate.into();
+                    Response::Ack
+                }
+                PollingRate::Extended(_) => return Err(Error::Unsupported),
+            },
+            Command::GetPollingRateExtended => match self.state.polling_rate {
+                PollingRate::Extended(polling_rate) => Response::PollingRateExtended(polling_rate),
+                PollingRate::Normal(_) => return Err(Error::Unsupported),
+            },
+            Command::SetPollingRateExtended(polling_rate) => match self.state.polling_rate {
+                PollingRate::Extended(_) => {
+                    self.state.polling_rate = polling_rate.into();
+                    Response::Ack
+                }
+                PollingRate::Normal(_) => return Err(Error::Unsupported),
+            },
+            Command::SetMatrixEffect(var_store, led, effect) => {
+                let current = self.state.led_effects.get_mut(&led);
+                *current.ok_or(Error::Unsupported)? = effect;
+                if var_store == VarStoreId::VarStore {
+                    self.state.onboard_led_effects.insert(led, effect);
+                }
+                Response::Ack
+            }
+            Command::GetMatrixEffect(var_store, led) => {
+                let effect = if var_store == VarStoreId::VarStore {
+                    self.state.onboard_led_effects.get(&led)
+                } else {
+                    self.state.led_effects.get(&led)
+                };
+                Response::MatrixEffect(*effect.ok_or(Error::Unsupported)?)
+            }
+            Command::GetLedBrightness(var_store, led) => {
+                let brightness = if var_store == VarStoreId::VarStore {
+                    self.state.onboard_led_brightness.get(&led)
+                } else {
+                    self.state.led_brightness.get(&led)
+                };
+                Response::LedBrightness(*brightness.ok_or(Error::Unsupported)?)
+            }
+            Command::SetLedBrightness(var_store, led, brightness) => {
+                let current = self.state.led_brightness.get_mut(&led);
+                *current.ok_or(Error::Unsupported)? = brightness;
+                if var_store == VarStoreId::VarStore {
+                    self.state.onboard_led_brightness.insert(led, brightness);
+                }
+                Response::Ack
+            }
+        };
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl RazerTransport for SimulatedMouse {
+    async fn send_report(&self, report: &[u8]) -> Result<()> {
+        let hang = self.0.lock().unwrap().take_fault(SimulatedFault::Hang);
+        if hang {
+            std::future::pending::<()>().await;
+        }
+
+        let mut inner = self.0.lock().unwrap();
+        if inner.take_fault(SimulatedFault::Disconnect) {
+            return Err(TransferError::Disconnected.into());
+        }
+        if inner.take_fault(SimulatedFault::Stall) {
+            return Err(TransferError::Stall.into());
+        }
+
+        // Like the real device, stall on a report of the wrong size
+        let request = RazerMessage::read_from_bytes(report).map_err(|_| TransferError::Stall)?;
+        let response = inner.handle(request);
+        inner.previous_response = inner.response.replace(response);
+        Ok(())
+    }
+
+    async fn receive_report(&self) -> Result<Vec<u8>> {
+        let mut inner = self.0.lock().unwrap();
+        if inner.take_fault(SimulatedFault::Stall) {
+            return Err(TransferError::Stall.into());
+        }
+
+        let response = if inner.take_fault(SimulatedFault::StaleResponse) {
+            inner.previous_response.clone()
+        } else {
+            inner.response.clone()
+        };
+        let Some(mut response) = response else {
+            // Nothing was sent yet, the device reports an empty report
+            return Ok(vec![0; RAZER_REPORT_SIZE]);
+        };
+        if inner.take_fault(SimulatedFault::Busy) {
+            response.set_status(RazerStatus::Busy);
+            response.update_crc();
+        }
+        let mut data = response.as_bytes().to_vec();
+        if inner.take_fault(SimulatedFault::CorruptCrc) {
+            data[RAZER_REPORT_SIZE - 2] ^= 0xFF;
+        }
+        Ok(data)
+    }
+}
@@ -0,0 +1,130 @@
+use async_trait::async_trait;
+use nusb::{
+    transfer::{ControlIn, ControlOut, ControlType, Recipient},
+    Device, Interface,
+};
+
+use crate::{
+    common::{HID_REQ_GET_REPORT, HID_REQ_SET_REPORT, RAZER_REPORT_SIZE},
+    Result,
+};
+
+/// Moves raw Razer reports (`RAZER_REPORT_SIZE` bytes each) between the driver and a device.
+///
+/// The device helpers only ever talk to a `RazerTransport`, so anything that can answer
+/// reports (a real mouse, an emulator, a recording) can stand in for the hardware.
+#[async_trait]
+pub trait RazerTransport: Send + Sync {
+    /// Send a report to the device (HID SET_REPORT).
+    async fn send_report(&self, report: &[u8]) -> Result<()>;
+    /// Read the device's current response report (HID GET_REPORT).
+    async fn receive_report(&self) -> Result<Vec<u8>>;
+    /// Hand the device back once done with it. Dropping the transport does the same, but
+    /// can't report failures.
+    fn release(self: Box<Self>) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Sends reports as HID class control transfers on a claimed USB interface.
+pub struct NusbTransport {
+    interface: Option<Interface>,
+    /// Set when the kernel driver was detached to claim the interface, to reattach it after
+    kernel_driver: Option<Device>,
+}
+
+impl NusbTransport {
+    pub fn new(interface: Interface) -> Self {
+        NusbTransport {
+            interface: Some(interface),
+            kernel_driver: None,
+        }
+    }
+
+    /// Detach the kernel driver from `interface_number` and claim it. The driver is reattached
+    /// when the transport is released or dropped, so the mouse keeps working as a pointer.
+    pub fn detach_and_claim(device: Device, interface_number: u8) -> Result<Self> {
+        // Fails when no driver is bound, which is fine, claiming tells us if it's still in the way
+        let _ = device.detach_kernel_driver(interface_number);
+        let mut transport = NusbTransport {
+            interface: None,
+            kernel_driver: Some(device.clone()),
+        };
+        // On failure, dropping the transport reattaches the driver we just detached
+        transport.interface = Some(device.claim_interface(interface_number)?);
+        Ok(transport)
+    }
+
+    fn interface(&self) -> &Interface {
+        self.interface
+            .as_ref()
+            .expect("The interface is only taken when releasing")
+    }
+
+    /// Release the interface, then give it back to the kernel driver if it was detached.
+    fn release_interface(&mut self) -> Result<()> {
+        let Some(interface) = self.interface.take() else {
+            return Ok(());
+        };
+        let interface_number = interface.interface_number();
+        drop(interface);
+        match self.kernel_driver.take() {
+            Some(device) => Ok(device.attach_kernel_driver(interface_number)?),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for NusbTransport {
+    fn drop(&mut self) {
+        let _ = self.release_interface();
+    }
+}
+
+#[async_trait]
+impl RazerTransport for NusbTransport {
+    async fn send_report(&self, report: &[u8]) -> Result<()> {
+        let control_message = usb_out_message(report);
+        self.interface()
+            .control_out(control_message)
+            .await
+            .into_result()?;
+        Ok(())
+    }
+
+    async fn receive_report(&self) -> Result<Vec<u8>> {
+        let control_message = usb_in_message();
+        let data = self
+            .interface()
+            .control_in(control_message)
+            .await
+            .into_result()?;
+        Ok(data)
+    }
+
+    fn release(mut self: Box<Self>) -> Result<()> {
+        self.release_interface()
+    }
+}
+
+fn usb_out_message(data: &[u8]) -> ControlOut<'_> {
+    ControlOut {
+        control_type: ControlType::Class,
+        recipient: Recipient::Interface,
+        request: HID_REQ_SET_REPORT,
+        value: 0x300,
+        index: 0x00,
+        data,
+    }
+}
+
+fn usb_in_message() -> ControlIn {
+    ControlIn {
+        control_type: ControlType::Class,
+        recipient: Recipient::Interface,
+        request: HID_REQ_GET_REPORT,
+        value: 0x300,
+        index: 0x00,
+        length: RAZER_REPORT_SIZE as u16,
+    }
+}
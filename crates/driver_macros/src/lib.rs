@@ -30,6 +30,10 @@ struct DeviceId {
 
 struct DeviceDef {
     transaction_id: u8,
+    /// `Normal` or `Extended`, the `PollingRateFamily` variant the device uses
+    polling_rate: Option<Ident>,
+    /// The `LedId` variants the device has
+    leds: Vec<Ident>,
     functions: Vec<FunctionMapping>,
 }
 
@@ -76,6 +80,36 @@ impl Parse for SharedDeviceDef {
         let transaction_id: u8 = content.parse::<LitInt>()?.base10_parse()?;
         content.parse::<Token![,]>()?;
 
+        // Then optionally the polling rate family and the LEDs, in any order
+        let mut polling_rate = None;
+        let mut leds = Vec::new();
+        while content.peek(Ident) && content.peek2(Token![=]) {
+            let setting = content.parse::<Ident>()?;
+            content.parse::<Token![=]>()?;
+            if setting == "polling_rate" {
+                let family = content.parse::<Ident>()?;
+                if family != "Normal" && family != "Extended" {
+                    return Err(syn::Error::new(
+                        family.span(),
+                        "Polling rate must be \"Normal\" or \"Extended\"",
+                    ));
+                }
+                polling_rate = Some(family);
+            } else if setting == "leds" {
+                let list;
+                bracketed!(list in content);
+                leds = Punctuated::<Ident, Token![,]>::parse_terminated(&list)?
+                    .into_iter()
+                    .collect();
+            } else {
+                return Err(syn::Error::new(
+                    setting.span(),
+                    "Expected \"polling_rate = Normal|Extended\" or \"leds = [Logo, ...]\"",
+                ));
+            }
+            content.parse::<Token![,]>()?;
+        }
+
         // Zero or more feature: impl_fn mappings in the rbaces
         let functions = Punctuated::<FunctionMapping, Token![,]>::parse_terminated(&content)?
             .into_iter()
@@ -84,6 +118,8 @@ impl Parse for SharedDeviceDef {
             device_ids,
             def: DeviceDef {
                 transaction_id,
+                polling_rate,
+                leds,
                 functions,
             },
         })
@@ -147,18 +183,24 @@ impl Parse for FunctionMapping {
 ///     - Defines the `FeatureSet` trait on it, only implementing listed methods using their described impls
 ///     - Defines the product_id of that device
 ///     - Adds a match arm to the `get_device_impl`, which maps from its product_id to its custom struct
+///       wrapping the `CommandQueue` it sends commands through
+///     - Adds a match arm to the `shared_product_ids`, which maps from its product_id to those of
+///       every device defined along with it, e.g. the wired and wireless ids of one mouse
 /// We then end up with implementations of subsets of `FeatureSet`'s methods for
 /// each device, as well as a method `get_device_impl` to take a product_id and return a `Box<dyn FeatureSet>` or error.
 ///
 /// Example use:
-/// ```
+/// ```ignore
 /// device_impls!([
 ///     DeathadderV2ProWired    0x007C |
 ///     DeathadderV2ProWireless 0x007D
 ///     {
 ///         transaction_id = 0x3f,
+///         polling_rate = Normal,
+///         leds = [Logo, ScrollWheel],
 ///         get_dpi,
 ///         set_dpi,
+///         get_polling_rate,
 ///     },
 ///     ViperMini 0x008A {
 ///         transaction_id = 0xXX,
@@ -173,6 +215,7 @@ pub fn device_impls(input: TokenStream) -> TokenStream {
 }
 
 fn device_impls_inner(device_defs: &DeviceDefs) -> TokenStream2 {
+    let shared_ids = shared_product_ids_inner(device_defs);
     let device_defs: Vec<SingleDeviceDef> = device_defs
         .0
         .iter()
@@ -191,75 +234,171 @@ fn device_impls_inner(device_defs: &DeviceDefs) -> TokenStream2 {
     let device_impls = device_defs.iter().map(device_impl_inner);
 
     quote! {
-        fn get_device_impl(product_id: u16, interface: Interface) -> Result<Box<dyn FeatureSet>> {
+        fn get_device_impl(product_id: u16, queue: CommandQueue) -> Result<Box<dyn FeatureSet>> {
             match product_id {
                 #(
-                id if id == #caps_names => Ok(Box::new(#pascal_names(interface))),
+                id if id == #caps_names => Ok(Box::new(#pascal_names(queue))),
                 )*
-                _ => Err(anyhow!("Unsupported device")),
+                _ => Err(Error::Unsupported),
             }
         }
 
+        #shared_ids
+
         #(
             #device_impls
         )*
     }
 }
 
+fn shared_product_ids_inner(device_defs: &DeviceDefs) -> TokenStream2 {
+    let arms = device_defs.0.iter().map(|shared_def| {
+        let caps_names: Vec<Ident> = shared_def
+            .device_ids
+            .iter()
+            .map(DeviceId::caps_name)
+            .collect();
+        quote! {
+            id if [#(#caps_names),*].contains(&id) => &[#(#caps_names),*],
+        }
+    });
+
+    quote! {
+        fn shared_product_ids(product_id: u16) -> &'static [u16] {
+            match product_id {
+                #(#arms)*
+                _ => &[],
+            }
+        }
+    }
+}
+
 fn device_impl_inner(device_def: &SingleDeviceDef<'_>) -> TokenStream2 {
     let SingleDeviceDef { device_id, def } = device_def;
     let caps_name = device_id.caps_name();
     let pascal_name = device_id.pascal_name();
     let product_id = device_id.product_id;
     let transaction_id = def.transaction_id;
+    let polling_rate_family = def
+        .polling_rate
+        .as_ref()
+        .map(|family| quote! { PollingRateFamily::#family });
 
     let fn_impls: syn::Result<Vec<TokenStream2>> = def.functions.iter().map(|fn_map| {
             let FunctionMapping { feature, impl_fn } = fn_map;
             let feature_str = feature.to_string();
             match feature_str.as_str() {
-                "get_dpi" => Ok(quote! {
-                    async fn get_dpi(&self) -> Result<Dpi> {
-                        #impl_fn(self.0.clone(), #transaction_id, VarStoreId::NoStore).await
+                "get_firmware_version" => Ok(quote! {
+                    async fn get_firmware_version(&self) -> Result<FirmwareVersion> {
+                        #impl_fn(&self.0, #transaction_id).await
                     }
                 }),
-                "set_dpi" => Ok(quote! {
-                    async fn set_dpi(&self, dpi: Dpi) -> Result<()> {
-                        #impl_fn(self.0.clone(), #transaction_id, VarStoreId::NoStore, dpi).await
+                "get_serial" => Ok(quote! {
+                    async fn get_serial(&self) -> Result<String> {
+                        #impl_fn(&self.0, #transaction_id).await
                     }
                 }),
-                "get_dpi_stages" => Ok(quote! {
-                    async fn get_dpi_stages(&self) -> Result<DpiStages> {
-                        #impl_fn(self.0.clone(), #transaction_id).await
+                "get_device_mode" => Ok(quote! {
+                    async fn get_device_mode(&self) -> Result<DeviceMode> {
+                        #impl_fn(&self.0, #transaction_id).await
                     }
                 }),
-                "set_dpi_stages" => Ok(quote! {
-                    async fn set_dpi_stages(&self, dpi_stages: &DpiStages) -> Result<()> {
-                        #impl_fn(self.0.clone(), #transaction_id, dpi_stages).await
+                "set_device_mode" => Ok(quote! {
+                    async fn set_device_mode(&self, mode: DeviceMode) -> Result<()> {
+                        #impl_fn(&self.0, #transaction_id, mode).await
+                    }
+                }),
+                "get_dpi" => Ok(quote! {
+                    async fn get_dpi(&self, persistence: Persistence) -> Result<Dpi> {
+                        #impl_fn(&self.0, #transaction_id, persistence.into()).await
                     }
                 }),
-                "get_polling_rate" => Ok(quote! {
-                    async fn get_polling_rate(&self) -> Result<PollingRate> {
-                        #impl_fn(self.0.clone(), #transaction_id).await
+                "set_dpi" => Ok(quote! {
+                    async fn set_dpi(&self, dpi: Dpi, persistence: Persistence) -> Result<()> {
+                        #impl_fn(&self.0, #transaction_id, persistence.into(), dpi).await
                     }
                 }),
-                "set_polling_rate" => Ok(quote! {
-                    async fn set_polling_rate(&self, polling_rate: PollingRate) -> Result<()> {
-                        #impl_fn(self.0.clone(), #transaction_id, polling_rate).await
+                "get_dpi_stages" => Ok(quote! {
+                    async fn get_dpi_stages(&self, persistence: Persistence) -> Result<DpiStages> {
+                        #impl_fn(&self.0, #transaction_id, persistence.into()).await
                     }
                 }),
+                "set_dpi_stages" => Ok(quote! {
+                    async fn set_dpi_stages(&self, dpi_stages: &DpiStages, persistence: Persistence) -> Result<()> {
+                        #impl_fn(&self.0, #transaction_id, persistence.into(), dpi_stages).await
+                    }
+                }),
+                "get_polling_rate" => match &polling_rate_family {
+                    Some(family) => Ok(quote! {
+                        async fn get_polling_rate(&self) -> Result<PollingRate> {
+                            #impl_fn(&self.0, #transaction_id, #family).await
+                        }
+                    }),
+                    None => Err(missing_polling_rate_family(feature)),
+                },
+                "set_polling_rate" => match &polling_rate_family {
+                    Some(family) => Ok(quote! {
+                        async fn set_polling_rate(&self, polling_rate: PollingRate) -> Result<()> {
+                            #impl_fn(&self.0, #transaction_id, #family, polling_rate).await
+                        }
+                    }),
+                    None => Err(missing_polling_rate_family(feature)),
+                },
                 "get_battery_level" => Ok(quote! {
                     async fn get_battery_level(&self) -> Result<f32> {
-                        #impl_fn(self.0.clone(), #transaction_id).await
+                        #impl_fn(&self.0, #transaction_id).await
                     }
                 }),
                 "get_charging_status" => Ok(quote! {
                     async fn get_charging_status(&self) -> Result<bool> {
-                        #impl_fn(self.0.clone(), #transaction_id).await
+                        #impl_fn(&self.0, #transaction_id).await
+                    }
+                }),
+                "get_idle_time" => Ok(quote! {
+                    async fn get_idle_time(&self) -> Result<Duration> {
+                        #impl_fn(&self.0, #transaction_id).await
+                    }
+                }),
+                "set_idle_time" => Ok(quote! {
+                    async fn set_idle_time(&self, idle_time: Duration) -> Result<()> {
+                        #impl_fn(&self.0, #transaction_id, idle_time).await
+                    }
+                }),
+                "get_low_battery_threshold" => Ok(quote! {
+                    async fn get_low_battery_threshold(&self) -> Result<u8> {
+                        #impl_fn(&self.0, #transaction_id).await
                     }
                 }),
-                "chroma_logo_matrix_effect" => Ok(quote! {
-                    async fn chroma_logo_matrix_effect(&self, effect: ExtendedMatrixEffect) -> Result<()> {
-                        #impl_fn(self.0.clone(), #transaction_id, effect).await
+                "set_low_battery_threshold" => Ok(quote! {
+                    async fn set_low_battery_threshold(&self, percent: u8) -> Result<()> {
+                        #impl_fn(&self.0, #transaction_id, percent).await
+                    }
+                }),
+                _ if is_led_feature(&feature_str) && def.leds.is_empty() => {
+                    Err(missing_leds(feature))
+                }
+                "chroma_matrix_effect" => Ok(quote! {
+                    async fn chroma_matrix_effect(&self, led: LedId, effect: ExtendedMatrixEffect, persistence: Persistence) -> Result<()> {
+                        check_led(self.get_leds(), led)?;
+                        #impl_fn(&self.0, #transaction_id, persistence.into(), led, effect).await
+                    }
+                }),
+                "get_chroma_matrix_effect" => Ok(quote! {
+                    async fn get_chroma_matrix_effect(&self, led: LedId, persistence: Persistence) -> Result<ExtendedMatrixEffect> {
+                        check_led(self.get_leds(), led)?;
+                        #impl_fn(&self.0, #transaction_id, persistence.into(), led).await
+                    }
+                }),
+                "get_led_brightness" => Ok(quote! {
+                    async fn get_led_brightness(&self, led: LedId, persistence: Persistence) -> Result<u8> {
+                        check_led(self.get_leds(), led)?;
+                        #impl_fn(&self.0, #transaction_id, persistence.into(), led).await
+                    }
+                }),
+                "set_led_brightness" => Ok(quote! {
+                    async fn set_led_brightness(&self, led: LedId, brightness: u8, persistence: Persistence) -> Result<()> {
+                        check_led(self.get_leds(), led)?;
+                        #impl_fn(&self.0, #transaction_id, persistence.into(), led, brightness).await
                     }
                 }),
                 _ => {
@@ -275,16 +414,66 @@ fn device_impl_inner(device_def: &SingleDeviceDef<'_>) -> TokenStream2 {
         }
     };
 
+    let polling_rate_family_impl = polling_rate_family.map(|family| {
+        quote! {
+            fn get_polling_rate_family(&self) -> Option<PollingRateFamily> {
+                Some(#family)
+            }
+        }
+    });
+
+    let leds = &def.leds;
+    let leds_impl = (!leds.is_empty()).then(|| {
+        quote! {
+            fn get_leds(&self) -> &'static [LedId] {
+                &[#(LedId::#leds),*]
+            }
+        }
+    });
+
     quote! {
-        pub(crate) const #caps_name: u16 = #product_id;
-        struct #pascal_name(Interface);
+        pub const #caps_name: u16 = #product_id;
+        struct #pascal_name(CommandQueue);
         #[async_trait]
         impl FeatureSet for #pascal_name {
+            #polling_rate_family_impl
+            #leds_impl
             #(#fn_impls)*
         }
     }
 }
 
+fn missing_polling_rate_family(feature: &Ident) -> syn::Error {
+    syn::Error::new(
+        feature.span(),
+        format!(
+            "{} needs \"polling_rate = Normal\" or \"polling_rate = Extended\" after the transaction id",
+            feature
+        ),
+    )
+}
+
+/// Whether a feature takes an `LedId`, which must be one the device declares
+fn is_led_feature(feature: &str) -> bool {
+    matches!(
+        feature,
+        "chroma_matrix_effect"
+            | "get_chroma_matrix_effect"
+            | "get_led_brightness"
+            | "set_led_brightness"
+    )
+}
+
+fn missing_leds(feature: &Ident) -> syn::Error {
+    syn::Error::new(
+        feature.span(),
+        format!(
+            "{} needs \"leds = [Logo, ...]\" after the transaction id",
+            feature
+        ),
+    )
+}
+
 /// Find duplicate product IDs in a list of device definitions for debugging.
 fn find_first_duplicate<'a, T>(device_ids: T) -> Option<syn::Error>
 where
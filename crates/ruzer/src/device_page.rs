@@ -1,34 +1,66 @@
+use std::{sync::Arc, time::Duration};
+
 use adw::prelude::*;
 use driver::{
     batched::{BatchedFeatureSet, DeviceSettings},
-    common::NormalPollingRate,
+    chroma::{Color, ExtendedMatrixEffect, LedId},
+    common::{
+        Dpi, Persistence, PollingRate, RAZER_MAX_IDLE_TIME, RAZER_MAX_LOW_BATTERY_THRESHOLD,
+        RAZER_MIN_IDLE_TIME, RAZER_MIN_LOW_BATTERY_THRESHOLD,
+    },
+    devices::{DeviceEvent, RazerDevice},
 };
-use nusb::DeviceInfo;
 use relm4::prelude::*;
+use tokio::sync::Mutex;
 
 mod dpi_stages;
+mod lighting;
 
 pub struct DevicePage {
-    usb_device_info: Option<nusb::DeviceInfo>,
+    device: Option<RazerDevice>,
+    /// Counts the devices shown, to drop what finishes for one that isn't anymore
+    generation: u64,
+    /// Held while the device is claimed for a refresh or apply
+    claim_lock: Arc<Mutex<()>>,
+    /// Why the device couldn't be claimed or released
+    error: Option<String>,
     razer_device_info: driver::batched::DeviceInfo,
     dpi_stages_list: relm4::Controller<dpi_stages::DpiStagesList>,
+    /// One listing per LED of the device
+    leds: FactoryVecDeque<lighting::LedListing>,
+    /// Model of the polling rate row, the rates of the device's polling rate family
+    polling_rates: gtk::StringList,
+    /// Whether editing the DPI changes X and Y together
+    dpi_linked: bool,
     pending_changes: DeviceSettings,
 }
 
 #[derive(Debug)]
 pub enum DevicePageMsg {
-    Update(nusb::DeviceInfo),
+    Update(RazerDevice),
     Refresh,
-    SelectPollingRate(driver::common::PollingRate),
-    SetDpi(Option<u16>),
+    /// Hz
+    SelectPollingRate(u16),
+    SetDpiX(Option<u16>),
+    SetDpiY(Option<u16>),
+    LinkDpi(bool),
     SetDpiStages(driver::common::DpiStages),
+    SetIdleTime(Duration),
+    SetLowBatteryThreshold(u8),
+    /// Index in the effect row's choices
+    SelectLedEffect(LedId, u32),
+    SetLedColor(LedId, Color),
+    /// Run a wave or wheel effect the other way
+    ReverseLedEffect(LedId, bool),
+    SetLedBrightness(LedId, u8),
     Cancel,
     Apply,
 }
 
 #[derive(Debug)]
 pub enum DevicePageCommand {
-    Update(driver::batched::DeviceInfo),
+    /// Settings read back for the device of a generation
+    Update(u64, driver::Result<driver::batched::DeviceInfo>),
 }
 
 #[relm4::component(pub)]
@@ -51,10 +83,32 @@ impl Component for DevicePage {
                         DevicePageMsg::SetDpiStages(dpi_stages)
                     }
                 });
+        let leds = FactoryVecDeque::builder()
+            .launch(gtk::Box::default())
+            .forward(sender.input_sender(), |output| match output {
+                lighting::LedListingOutput::SelectEffect(led, index) => {
+                    DevicePageMsg::SelectLedEffect(led, index)
+                }
+                lighting::LedListingOutput::SetColor(led, color) => {
+                    DevicePageMsg::SetLedColor(led, color)
+                }
+                lighting::LedListingOutput::Reverse(led, reversed) => {
+                    DevicePageMsg::ReverseLedEffect(led, reversed)
+                }
+                lighting::LedListingOutput::SetBrightness(led, brightness) => {
+                    DevicePageMsg::SetLedBrightness(led, brightness)
+                }
+            });
         let model = Self {
-            usb_device_info: None,
+            device: None,
+            generation: 0,
+            claim_lock: Arc::default(),
+            error: None,
             razer_device_info: driver::batched::DeviceInfo::default(),
             dpi_stages_list,
+            leds,
+            polling_rates: gtk::StringList::new(&[]),
+            dpi_linked: true,
             pending_changes: DeviceSettings::default(),
         };
         let widgets = view_output!();
@@ -64,37 +118,85 @@ impl Component for DevicePage {
 
     fn update(&mut self, message: Self::Input, sender: ComponentSender<Self>, _root: &Self::Root) {
         match message {
-            DevicePageMsg::Update(usb_device_info) => {
-                self.update(&sender, usb_device_info);
+            DevicePageMsg::Update(device) => {
+                self.update(&sender, device);
             }
             DevicePageMsg::Refresh => {
-                if let Some(usb_device_info) = self.usb_device_info.clone() {
-                    self.update(&sender, usb_device_info);
+                self.refresh(&sender);
+            }
+            DevicePageMsg::SelectPollingRate(hz) => {
+                let family = self.razer_device_info.polling_rate_family;
+                if let Some(polling_rate) = family.and_then(|family| family.rate(hz)) {
+                    self.pending_changes.polling_rate = Some(polling_rate);
                 }
             }
-            DevicePageMsg::SelectPollingRate(polling_rate) => {
-                self.pending_changes.polling_rate = Some(polling_rate);
+            DevicePageMsg::SetDpiX(x) => {
+                let y = if self.dpi_linked {
+                    x
+                } else {
+                    self.current_dpi().map(|dpi| dpi.y)
+                };
+                self.set_pending_dpi(x.zip(y));
+            }
+            DevicePageMsg::SetDpiY(y) => {
+                let x = self.current_dpi().map(|dpi| dpi.x);
+                self.set_pending_dpi(x.zip(y));
             }
-            DevicePageMsg::SetDpi(dpi) => {
-                let dpi_range = self.razer_device_info.dpi_range;
-                let dpi = dpi
-                    .filter(|dpi| dpi_range.0 <= *dpi && *dpi <= dpi_range.1)
-                    .map(|dpi| dpi.into());
-                self.pending_changes.dpi = dpi;
+            DevicePageMsg::LinkDpi(linked) => {
+                self.dpi_linked = linked;
+                // Y follows X as soon as they're linked
+                if let Some(dpi) = self.current_dpi().filter(|dpi| linked && dpi.x != dpi.y) {
+                    self.set_pending_dpi(Some((dpi.x, dpi.x)));
+                }
             }
             DevicePageMsg::Apply => {
                 self.apply_changes(&sender);
             }
             DevicePageMsg::Cancel => {
                 self.pending_changes = DeviceSettings::default();
+                self.dpi_linked = is_symmetric(self.razer_device_info.dpi);
                 if let Some(dpi_stages) = self.razer_device_info.dpi_stages.clone() {
                     self.dpi_stages_list
                         .emit(dpi_stages::DpiStagesListMsg::Update(dpi_stages))
                 }
+                self.show_leds();
             }
             DevicePageMsg::SetDpiStages(dpi_stages) => {
                 self.pending_changes.dpi_stages = Some(dpi_stages);
             }
+            DevicePageMsg::SetIdleTime(idle_time) => {
+                self.pending_changes.idle_time = Some(idle_time);
+            }
+            DevicePageMsg::SetLowBatteryThreshold(low_battery_threshold) => {
+                self.pending_changes.low_battery_threshold = Some(low_battery_threshold);
+            }
+            DevicePageMsg::SelectLedEffect(led, index) => {
+                let effect = self
+                    .led_effect(led)
+                    .and_then(|effect| lighting::effect_at(index, effect));
+                if let Some(effect) = effect {
+                    self.pending_changes.led_effects.insert(led, effect);
+                }
+                self.show_leds();
+            }
+            DevicePageMsg::SetLedColor(led, color) => {
+                if let Some(effect) = self.led_effect(led) {
+                    let effect = lighting::with_color(effect, color);
+                    self.pending_changes.led_effects.insert(led, effect);
+                }
+                self.show_leds();
+            }
+            DevicePageMsg::ReverseLedEffect(led, reversed) => {
+                if let Some(effect) = self.led_effect(led) {
+                    let effect = lighting::with_reversed(effect, reversed);
+                    self.pending_changes.led_effects.insert(led, effect);
+                }
+                self.show_leds();
+            }
+            DevicePageMsg::SetLedBrightness(led, brightness) => {
+                self.pending_changes.led_brightness.insert(led, brightness);
+                self.show_leds();
+            }
         }
     }
 
@@ -105,14 +207,13 @@ impl Component for DevicePage {
         _root: &Self::Root,
     ) {
         match message {
-            DevicePageCommand::Update(razer_device_info) => {
-                // Reset page and update with new device info
-                self.pending_changes = DeviceSettings::default();
-                self.razer_device_info = razer_device_info.clone();
-                if let Some(dpi_stages) = razer_device_info.dpi_stages {
-                    self.dpi_stages_list
-                        .emit(dpi_stages::DpiStagesListMsg::Update(dpi_stages))
-                }
+            DevicePageCommand::Update(generation, _) if generation != self.generation => {}
+            DevicePageCommand::Update(_, Ok(razer_device_info)) => {
+                self.error = None;
+                self.show_device_info(razer_device_info);
+            }
+            DevicePageCommand::Update(_, Err(err)) => {
+                self.error = Some(err.to_string());
             }
         }
     }
@@ -124,6 +225,14 @@ impl Component for DevicePage {
                 set_spacing: 10,
                 set_margin_start: 20,
                 set_margin_end: 20,
+                adw::Banner {
+                    #[watch]
+                    set_title: model.error.as_deref().unwrap_or_default(),
+                    #[watch]
+                    set_revealed: model.error.is_some(),
+                    set_button_label: Some("Retry"),
+                    connect_button_clicked => DevicePageMsg::Refresh,
+                },
                 // Device and Battery Info Section
                 gtk::Box {
                     set_orientation: gtk::Orientation::Vertical,
@@ -132,9 +241,20 @@ impl Component for DevicePage {
                     // Device Name
                     gtk::Label {
                         #[watch]
-                        set_label?: &model.usb_device_info.clone().and_then(|info| info.product_string().map(|x| x.to_owned())),
+                        set_label?: &model.device.as_ref().and_then(|device| device.name().map(|x| x.to_owned())),
                         set_css_classes: &["title-1"],
                     },
+                    // Serial and firmware, to tell identical mice apart and for bug reports
+                    gtk::Label {
+                        #[watch]
+                        set_label: &format!(
+                            "Serial: {} · Firmware: {}",
+                            model.razer_device_info.serial.as_deref().unwrap_or("N/A"),
+                            model.razer_device_info.firmware_version.map_or("N/A".into(), |version| version.to_string()),
+                        ),
+                        set_selectable: true,
+                        set_css_classes: &["caption", "dim-label"],
+                    },
                     // Battery Info
                     gtk::Box {
                         set_homogeneous: true,
@@ -170,63 +290,143 @@ impl Component for DevicePage {
                         set_css_classes: &["boxed-list"],
                         // Polling Rate Section
                         adw::ComboRow {
-                            // TODO: Handle extended polling rates
                             set_title: "Polling Rate",
                             #[watch]
+                            set_sensitive: model.razer_device_info.polling_rate_family.is_some(),
+                            #[watch]
                             set_selected: {
-                                // In StringList model below
-                                let rate_to_index = |rate| match rate {
-                                    driver::common::PollingRate::Normal(NormalPollingRate::Rate125) => 0,
-                                    driver::common::PollingRate::Normal(NormalPollingRate::Rate500) => 1,
-                                    driver::common::PollingRate::Normal(NormalPollingRate::Rate1000) => 2,
-                                    _ => gtk::INVALID_LIST_POSITION,
-                                };
                                 // Use current selected rate if set, otherwise use device info
-                                if let Some(polling_rate) = model.pending_changes.polling_rate {
-                                    rate_to_index(polling_rate)
-                                } else if let Some(polling_rate) = model.razer_device_info.polling_rate {
-                                    rate_to_index(polling_rate)
-                                } else {
-                                    gtk::INVALID_LIST_POSITION
+                                let polling_rate = model
+                                    .pending_changes
+                                    .polling_rate
+                                    .or(model.razer_device_info.polling_rate);
+                                match (polling_rate, model.razer_device_info.polling_rate_family) {
+                                    (Some(polling_rate), Some(family)) => family
+                                        .rates()
+                                        .iter()
+                                        .position(|&rate| u16::from(rate) == u16::from(polling_rate))
+                                        .map_or(gtk::INVALID_LIST_POSITION, |index| index as u32),
+                                    _ => gtk::INVALID_LIST_POSITION,
                                 }
                             },
-                            #[wrap(Some)]
-                            set_model = &gtk::StringList::new(&["125", "500", "1000"]),
+                            set_model: Some(&model.polling_rates),
                             connect_selected_notify[sender] => move |combo_row| {
                                 let selected_string = combo_row
                                     .selected_item()
                                     .and_then(|obj| obj.downcast::<gtk::StringObject>().ok())
                                     .map(|s| Into::<String>::into(s.string()));
-                                let polling_rate = selected_string
-                                    .and_then(|s| s.parse::<u16>().ok())
-                                    .and_then(|dpi| NormalPollingRate::try_from(dpi).ok());
-                                if let Some(polling_rate) = polling_rate {
-                                    sender.input(DevicePageMsg::SelectPollingRate(polling_rate.into()));
+                                if let Some(hz) = selected_string.and_then(|s| s.parse::<u16>().ok()) {
+                                    sender.input(DevicePageMsg::SelectPollingRate(hz));
                                 }
                             },
                         },
                         // DPI Section
+                        adw::SwitchRow {
+                            set_title: "Same DPI for X and Y",
+                            #[watch]
+                            set_active: model.dpi_linked,
+                            connect_active_notify[sender] => move |switch_row| {
+                                sender.input(DevicePageMsg::LinkDpi(switch_row.is_active()));
+                            },
+                        },
                         adw::EntryRow {
-                            set_title: "DPI",
+                            #[watch]
+                            set_title: if model.dpi_linked { "DPI" } else { "DPI X" },
                             set_show_apply_button: true,
                             #[watch]
-                            set_text: &{
-                                if let Some(dpi) = model.pending_changes.dpi {
-                                    dpi.x.to_string()
-                                } else if let Some(dpi) = model.razer_device_info.dpi {
-                                    dpi.x.to_string()
-                                } else {
-                                    "".to_string()
-                                }
+                            set_text: &model.current_dpi().map_or("".to_string(), |dpi| dpi.x.to_string()),
+                            connect_apply[sender] => move |entry_row| {
+                                let x = entry_row.text().parse::<u16>().ok();
+                                sender.input(DevicePageMsg::SetDpiX(x));
                             },
+                        },
+                        adw::EntryRow {
+                            set_title: "DPI Y",
+                            set_show_apply_button: true,
+                            #[watch]
+                            set_visible: !model.dpi_linked,
+                            #[watch]
+                            set_text: &model.current_dpi().map_or("".to_string(), |dpi| dpi.y.to_string()),
                             connect_apply[sender] => move |entry_row| {
-                                let dpi = entry_row.text().parse::<u16>().ok();
-                                sender.input(DevicePageMsg::SetDpi(dpi));
+                                let y = entry_row.text().parse::<u16>().ok();
+                                sender.input(DevicePageMsg::SetDpiY(y));
                             },
                         },
                     },
                     // DPI Stages Section
                     model.dpi_stages_list.widget(),
+                    // Power Section
+                    gtk::Label {
+                        set_label: "Power",
+                        set_halign: gtk::Align::Start,
+                        set_css_classes: &["heading"]
+                    },
+                    gtk::ListBox {
+                        set_selection_mode: gtk::SelectionMode::None,
+                        set_css_classes: &["boxed-list"],
+                        adw::SpinRow {
+                            set_title: "Sleep After",
+                            set_subtitle: "Seconds without movement",
+                            set_adjustment: Some(&gtk::Adjustment::new(
+                                RAZER_MIN_IDLE_TIME.as_secs_f64(),
+                                RAZER_MIN_IDLE_TIME.as_secs_f64(),
+                                RAZER_MAX_IDLE_TIME.as_secs_f64(),
+                                30.,
+                                60.,
+                                0.,
+                            )),
+                            #[watch]
+                            set_sensitive: model.razer_device_info.idle_time.is_some(),
+                            #[watch]
+                            set_value: model
+                                .pending_changes
+                                .idle_time
+                                .or(model.razer_device_info.idle_time)
+                                .unwrap_or(RAZER_MIN_IDLE_TIME)
+                                .as_secs_f64(),
+                            connect_value_notify[sender] => move |spin_row| {
+                                let idle_time = Duration::from_secs(spin_row.value() as u64);
+                                sender.input(DevicePageMsg::SetIdleTime(idle_time));
+                            },
+                        },
+                        adw::SpinRow {
+                            set_title: "Low Battery Threshold",
+                            set_subtitle: "Percent at which the mouse starts saving power",
+                            set_adjustment: Some(&gtk::Adjustment::new(
+                                RAZER_MIN_LOW_BATTERY_THRESHOLD.into(),
+                                RAZER_MIN_LOW_BATTERY_THRESHOLD.into(),
+                                RAZER_MAX_LOW_BATTERY_THRESHOLD.into(),
+                                1.,
+                                5.,
+                                0.,
+                            )),
+                            #[watch]
+                            set_sensitive: model.razer_device_info.low_battery_threshold.is_some(),
+                            #[watch]
+                            set_value: model
+                                .pending_changes
+                                .low_battery_threshold
+                                .or(model.razer_device_info.low_battery_threshold)
+                                .unwrap_or(RAZER_MIN_LOW_BATTERY_THRESHOLD)
+                                .into(),
+                            connect_value_notify[sender] => move |spin_row| {
+                                let threshold = spin_row.value() as u8;
+                                sender.input(DevicePageMsg::SetLowBatteryThreshold(threshold));
+                            },
+                        },
+                    },
+                    // Lighting Section
+                    gtk::Label {
+                        set_label: "Lighting",
+                        set_halign: gtk::Align::Start,
+                        set_css_classes: &["heading"],
+                        #[watch]
+                        set_visible: !model.razer_device_info.leds.is_empty(),
+                    },
+                    model.leds.widget() -> &gtk::Box {
+                        set_orientation: gtk::Orientation::Vertical,
+                        set_spacing: 20,
+                    },
                 },
                 // Apply Section
                 gtk::Box {
@@ -251,33 +451,164 @@ impl Component for DevicePage {
 }
 
 impl DevicePage {
-    fn update(&mut self, sender: &ComponentSender<DevicePage>, usb_device_info: DeviceInfo) {
-        self.usb_device_info = Some(usb_device_info.clone());
+    fn update(&mut self, sender: &ComponentSender<DevicePage>, device: RazerDevice) {
+        self.device = Some(device);
+        // Whatever is still running for the previous device is ignored once it finishes
+        self.generation += 1;
+        self.error = None;
+        self.refresh(sender);
+    }
+
+    fn refresh(&self, sender: &ComponentSender<DevicePage>) {
+        self.run_claimed(sender, None);
+    }
+
+    fn apply_changes(&self, sender: &ComponentSender<DevicePage>) {
+        self.run_claimed(sender, Some(self.pending_changes.clone()));
+    }
 
-        // Run batched device info command on device if exists
+    /// Apply `changes`, if any, and read the settings back with the device claimed only
+    /// for as long as that takes. Released right after, so a mouse without a hidraw node
+    /// only has its kernel driver detached (and stops moving the pointer) meanwhile.
+    fn run_claimed(&self, sender: &ComponentSender<DevicePage>, changes: Option<DeviceSettings>) {
+        let Some(device) = self.device.clone() else {
+            return;
+        };
+        let generation = self.generation;
+        let claim_lock = self.claim_lock.clone();
         sender.oneshot_command(async move {
-            let device = driver::devices::RazerDevice::new(usb_device_info);
-            let device_claimed = device.claim().unwrap();
-            DevicePageCommand::Update(device_claimed.get_batched().await)
+            // A refresh and an apply can't both hold the claim, the second one would fail
+            let _claim_guard = claim_lock.lock().await;
+            let result = async {
+                let device_claimed = device.claim()?;
+                let mut events = device_claimed.events();
+                // Read back from where the changes went, what the mouse runs with unless saved
+                let persistence = changes
+                    .as_ref()
+                    .map_or(Persistence::default(), |changes| changes.persistence);
+                if let Some(changes) = changes {
+                    let _err = device_claimed.set_batched(&changes).await;
+                }
+                let mut razer_device_info = device_claimed.get_batched(persistence).await;
+                if let Ok(DeviceEvent::Reconnected) = events.try_recv() {
+                    // The mouse may have reset its settings while it was gone
+                    razer_device_info = device_claimed.get_batched(persistence).await;
+                }
+                device_claimed.release().await?;
+                Ok(razer_device_info)
+            }
+            .await;
+            DevicePageCommand::Update(generation, result)
         });
     }
 
-    fn apply_changes(&self, sender: &ComponentSender<DevicePage>) {
-        if let Some(device_info) = &self.usb_device_info {
-            let device_info = device_info.clone();
-            let pending_changes = self.pending_changes.clone();
-            sender.oneshot_command(async move {
-                let device = driver::devices::RazerDevice::new(device_info);
-                let device_claimed = device.claim().unwrap();
-                let _err = device_claimed.set_batched(&pending_changes).await;
-                DevicePageCommand::Update(device_claimed.get_batched().await)
-            });
+    /// The DPI shown, with pending changes applied
+    fn current_dpi(&self) -> Option<Dpi> {
+        self.pending_changes.dpi.or(self.razer_device_info.dpi)
+    }
+
+    /// Show the effect and brightness of each LED, with pending changes applied
+    fn show_leds(&mut self) {
+        let listings: Vec<lighting::LedListing> = self
+            .razer_device_info
+            .leds
+            .iter()
+            .map(|&led| lighting::LedListing {
+                led,
+                effect: self.led_effect(led),
+                brightness: self.led_brightness(led),
+            })
+            .collect();
+        let mut leds = self.leds.guard();
+        let same_leds = leds.len() == listings.len()
+            && leds
+                .iter()
+                .zip(&listings)
+                .all(|(shown, new)| shown.led == new.led);
+        if !same_leds {
+            leds.clear();
+            for listing in listings {
+                leds.push_back(listing);
+            }
+            return;
+        }
+        // Update the rows in place, rebuilding them would end a drag of a brightness slider
+        for (index, listing) in listings.into_iter().enumerate() {
+            if let Some(shown) = leds.get_mut(index) {
+                *shown = listing;
+            }
         }
     }
+
+    /// The effect shown for `led`, with pending changes applied
+    fn led_effect(&self, led: LedId) -> Option<ExtendedMatrixEffect> {
+        self.pending_changes
+            .led_effects
+            .get(&led)
+            .or(self.razer_device_info.led_effects.get(&led))
+            .copied()
+    }
+
+    /// The brightness shown for `led`, with pending changes applied
+    fn led_brightness(&self, led: LedId) -> Option<u8> {
+        self.pending_changes
+            .led_brightness
+            .get(&led)
+            .or(self.razer_device_info.led_brightness.get(&led))
+            .copied()
+    }
+
+    /// Set the pending DPI, or drop it if either axis is out of the device's range
+    fn set_pending_dpi(&mut self, dpi: Option<(u16, u16)>) {
+        let (min_dpi, max_dpi) = self.razer_device_info.dpi_range;
+        let in_range = |dpi: u16| min_dpi <= dpi && dpi <= max_dpi;
+        self.pending_changes.dpi = dpi
+            .filter(|&(x, y)| in_range(x) && in_range(y))
+            .map(Dpi::from);
+    }
+
+    fn show_device_info(&mut self, razer_device_info: driver::batched::DeviceInfo) {
+        // Reset page and update with new device info
+        self.pending_changes = DeviceSettings::default();
+        self.dpi_linked = is_symmetric(razer_device_info.dpi);
+        if razer_device_info.polling_rate_family != self.razer_device_info.polling_rate_family {
+            let rates: Vec<String> = razer_device_info
+                .polling_rate_family
+                .map_or(Vec::new(), |family| family.rates())
+                .iter()
+                .map(PollingRate::to_string)
+                .collect();
+            let rates: Vec<&str> = rates.iter().map(String::as_str).collect();
+            self.polling_rates
+                .splice(0, self.polling_rates.n_items(), &rates);
+        }
+        self.razer_device_info = razer_device_info.clone();
+        self.show_leds();
+        if let Some(dpi_stages) = razer_device_info.dpi_stages {
+            self.dpi_stages_list
+                .emit(dpi_stages::DpiStagesListMsg::Update(dpi_stages))
+        }
+    }
+}
+
+/// Unknown DPI counts as symmetric, which most mice are set to
+fn is_symmetric(dpi: Option<Dpi>) -> bool {
+    dpi.is_none_or(|dpi| dpi.x == dpi.y)
 }
 
 fn settings_changed(info: &driver::batched::DeviceInfo, pending: &DeviceSettings) -> bool {
     (pending.dpi.is_some() && pending.dpi != info.dpi)
         || (pending.dpi_stages.is_some() && pending.dpi_stages != info.dpi_stages)
         || (pending.polling_rate.is_some() && pending.polling_rate != info.polling_rate)
+        || (pending.idle_time.is_some() && pending.idle_time != info.idle_time)
+        || (pending.low_battery_threshold.is_some()
+            && pending.low_battery_threshold != info.low_battery_threshold)
+        || pending
+            .led_effects
+            .iter()
+            .any(|(led, effect)| info.led_effects.get(led) != Some(effect))
+        || pending
+            .led_brightness
+            .iter()
+            .any(|(led, brightness)| info.led_brightness.get(led) != Some(brightness))
 }
@@ -10,6 +10,7 @@ pub struct DpiStagesListing {
 
 #[derive(Debug)]
 pub enum DpiStagesListingOutput {
+    Edit(DynamicIndex, Dpi),
     Remove(DynamicIndex),
 }
 
@@ -26,9 +27,17 @@ impl FactoryComponent for DpiStagesListing {
     }
 
     view! {
-        adw::ActionRow {
-            set_activatable: false,
-            set_title: &self.dpi.x.to_string(),
+        // X, or XxY when they differ
+        adw::EntryRow {
+            set_title: "DPI",
+            set_text: &self.dpi.to_string(),
+            set_show_apply_button: true,
+            connect_apply[sender, index, dpi = self.dpi] => move |entry_row| {
+                match entry_row.text().parse::<Dpi>() {
+                    Ok(dpi) => sender.output(DpiStagesListingOutput::Edit(index.clone(), dpi)).unwrap(),
+                    Err(_) => entry_row.set_text(&dpi.to_string()),
+                }
+            },
             add_suffix = &gtk::Button {
                 set_has_frame: false,
                 set_valign: gtk::Align::Center,
@@ -49,6 +58,7 @@ pub struct DpiStagesList {
 #[derive(Debug)]
 pub enum DpiStagesListMsg {
     Add(Dpi),
+    Edit(DynamicIndex, Dpi),
     Update(DpiStages),
     Remove(DynamicIndex),
 }
@@ -73,6 +83,7 @@ impl Component for DpiStagesList {
         let dpi_stages = FactoryVecDeque::builder()
             .launch(gtk::ListBox::new())
             .forward(sender.input_sender(), |msg| match msg {
+                DpiStagesListingOutput::Edit(index, dpi) => DpiStagesListMsg::Edit(index, dpi),
                 DpiStagesListingOutput::Remove(index) => DpiStagesListMsg::Remove(index),
             });
         let model = DpiStagesList { dpi_stages };
@@ -95,6 +106,16 @@ impl Component for DpiStagesList {
                 self.sort_dpi_stages_list();
                 self.notify_output_dpi_stages(sender);
             }
+            DpiStagesListMsg::Edit(index, dpi) => {
+                {
+                    let mut dpi_stages_list = self.dpi_stages.guard();
+                    if let Some(listing) = dpi_stages_list.get_mut(index.current_index()) {
+                        listing.dpi = dpi;
+                    }
+                }
+                self.sort_dpi_stages_list();
+                self.notify_output_dpi_stages(sender);
+            }
             DpiStagesListMsg::Remove(index) => {
                 // Remove a DPI stage
                 {
@@ -131,13 +152,10 @@ impl Component for DpiStagesList {
                 set_selection_mode: gtk::SelectionMode::None,
                 set_css_classes: &["boxed-list"],
                 adw::EntryRow {
-                    set_title: "Add DPI stage",
+                    set_title: "Add DPI stage (X or XxY)",
                     set_show_apply_button: true,
                     connect_apply[sender] => move |entry_row| {
-                        let dpi = entry_row.text().parse::<u16>().ok()
-                            .map(|dpi| dpi.into());
-
-                        if let Some(dpi) = dpi {
+                        if let Ok(dpi) = entry_row.text().parse::<Dpi>() {
                             sender.input(DpiStagesListMsg::Add(dpi))
                         } else {
                             entry_row.set_text("");
@@ -159,7 +177,7 @@ impl DpiStagesList {
     }
 
     /// Look at the current widgets and extract the DPI stages
-    fn get_dpi_stages_from_current(&self) -> anyhow::Result<DpiStages> {
+    fn get_dpi_stages_from_current(&self) -> driver::Result<DpiStages> {
         let mut dpis: Vec<Dpi> = Vec::new();
         let mut active_stage = 0;
         for (index, listing) in self.dpi_stages.iter().enumerate() {
@@ -175,7 +193,7 @@ impl DpiStagesList {
     fn sort_dpi_stages_list(&mut self) {
         let mut dpi_stages_list = self.dpi_stages.guard();
         let mut new_list: Vec<_> = dpi_stages_list.iter().cloned().collect();
-        new_list.sort_by(|a, b| a.dpi.x.cmp(&b.dpi.x));
+        new_list.sort_by_key(|listing| (listing.dpi.x, listing.dpi.y));
 
         dpi_stages_list.clear();
         for dpi_stage_listing in new_list {
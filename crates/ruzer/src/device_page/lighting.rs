@@ -0,0 +1,244 @@
+use adw::prelude::*;
+use driver::chroma::{
+    BreathingEffect, Color, ExtendedMatrixEffect, LedId, StarlightEffect, WaveDirection,
+    WheelDirection,
+};
+use gtk::gdk;
+use relm4::prelude::*;
+
+/// The effect and brightness rows of one LED
+#[derive(Debug)]
+pub struct LedListing {
+    pub led: LedId,
+    /// With pending changes applied, `None` if the device didn't report it
+    pub effect: Option<ExtendedMatrixEffect>,
+    pub brightness: Option<u8>,
+}
+
+#[derive(Debug)]
+pub enum LedListingOutput {
+    /// Index in `EFFECTS`
+    SelectEffect(LedId, u32),
+    SetColor(LedId, Color),
+    Reverse(LedId, bool),
+    SetBrightness(LedId, u8),
+}
+
+#[relm4::factory(pub)]
+impl FactoryComponent for LedListing {
+    type ParentWidget = gtk::Box;
+    type CommandOutput = ();
+    type Input = ();
+    type Output = LedListingOutput;
+    type Init = LedListing;
+
+    fn init_model(init: Self::Init, _index: &Self::Index, _sender: FactorySender<Self>) -> Self {
+        init
+    }
+
+    view! {
+        gtk::ListBox {
+            set_selection_mode: gtk::SelectionMode::None,
+            set_css_classes: &["boxed-list"],
+            adw::ComboRow {
+                set_title: &format!("{} Effect", self.led),
+                set_model: Some(&gtk::StringList::new(&EFFECTS)),
+                #[watch]
+                set_sensitive: self.effect.is_some(),
+                #[watch]
+                set_selected: self.effect.map_or(gtk::INVALID_LIST_POSITION, effect_index),
+                connect_selected_notify[sender, led = self.led] => move |combo_row| {
+                    sender.output(LedListingOutput::SelectEffect(led, combo_row.selected())).unwrap();
+                },
+            },
+            adw::ActionRow {
+                set_title: &format!("{} Color", self.led),
+                #[watch]
+                set_sensitive: self.effect.and_then(effect_color).is_some(),
+                add_suffix = &gtk::ColorDialogButton {
+                    set_valign: gtk::Align::Center,
+                    set_dialog: &gtk::ColorDialog::builder().with_alpha(false).build(),
+                    #[watch]
+                    set_rgba: &to_rgba(self.effect.and_then(effect_color)),
+                    connect_rgba_notify[sender, led = self.led] => move |button| {
+                        let color = from_rgba(&button.rgba());
+                        sender.output(LedListingOutput::SetColor(led, color)).unwrap();
+                    },
+                },
+            },
+            adw::SwitchRow {
+                set_title: &format!("{} Reverse Direction", self.led),
+                #[watch]
+                set_sensitive: self.effect.and_then(effect_reversed).is_some(),
+                #[watch]
+                set_active: self.effect.and_then(effect_reversed).unwrap_or(false),
+                connect_active_notify[sender, led = self.led] => move |switch_row| {
+                    sender.output(LedListingOutput::Reverse(led, switch_row.is_active())).unwrap();
+                },
+            },
+            adw::ActionRow {
+                set_title: &format!("{} Brightness", self.led),
+                #[watch]
+                set_sensitive: self.brightness.is_some(),
+                add_suffix = &gtk::Scale {
+                    set_width_request: 200,
+                    set_adjustment: &gtk::Adjustment::new(0., 0., 255., 1., 16., 0.),
+                    set_digits: 0,
+                    set_draw_value: true,
+                    #[watch]
+                    set_value: self.brightness.unwrap_or(0).into(),
+                    connect_value_changed[sender, led = self.led] => move |scale| {
+                        let brightness = scale.value() as u8;
+                        sender.output(LedListingOutput::SetBrightness(led, brightness)).unwrap();
+                    },
+                },
+            },
+        },
+    }
+}
+
+/// Choices of the effect row, in the order of `effect_index`
+const EFFECTS: [&str; 8] = [
+    "Off",
+    "Static",
+    "Breathing",
+    "Spectrum",
+    "Wave",
+    "Reactive",
+    "Starlight",
+    "Wheel",
+];
+
+/// Razer green, for effects picked while the current one has no color
+const DEFAULT_COLOR: Color = Color {
+    r: 0x44,
+    g: 0xd6,
+    b: 0x2c,
+};
+const DEFAULT_REACTIVE_SPEED: u8 = 0x02;
+const DEFAULT_WAVE_SPEED: u8 = 0x28;
+const DEFAULT_STARLIGHT_SPEED: u8 = 0x02;
+
+fn effect_index(effect: ExtendedMatrixEffect) -> u32 {
+    match effect {
+        ExtendedMatrixEffect::None => 0,
+        ExtendedMatrixEffect::Static(_) => 1,
+        ExtendedMatrixEffect::Breathing(_) => 2,
+        ExtendedMatrixEffect::Spectrum => 3,
+        ExtendedMatrixEffect::Wave(..) => 4,
+        ExtendedMatrixEffect::Reactive(..) => 5,
+        ExtendedMatrixEffect::Starlight(..) => 6,
+        ExtendedMatrixEffect::Wheel(_) => 7,
+    }
+}
+
+/// The effect at `index` of `EFFECTS`, keeping what it can of `current`
+pub fn effect_at(index: u32, current: ExtendedMatrixEffect) -> Option<ExtendedMatrixEffect> {
+    if index == effect_index(current) {
+        return Some(current);
+    }
+    let color = effect_color(current).unwrap_or(DEFAULT_COLOR);
+    let effect = match index {
+        0 => ExtendedMatrixEffect::None,
+        1 => ExtendedMatrixEffect::Static(color),
+        2 => ExtendedMatrixEffect::Breathing(BreathingEffect::Single(color)),
+        3 => ExtendedMatrixEffect::Spectrum,
+        4 => ExtendedMatrixEffect::Wave(WaveDirection::Right, DEFAULT_WAVE_SPEED),
+        5 => ExtendedMatrixEffect::Reactive(color, DEFAULT_REACTIVE_SPEED),
+        6 => {
+            ExtendedMatrixEffect::Starlight(StarlightEffect::Single(color), DEFAULT_STARLIGHT_SPEED)
+        }
+        7 => ExtendedMatrixEffect::Wheel(WheelDirection::Clockwise),
+        _ => return None,
+    };
+    Some(effect)
+}
+
+/// The color the color row edits, the first one of a dual breathing or starlight effect
+fn effect_color(effect: ExtendedMatrixEffect) -> Option<Color> {
+    match effect {
+        ExtendedMatrixEffect::Static(color)
+        | ExtendedMatrixEffect::Breathing(BreathingEffect::Single(color))
+        | ExtendedMatrixEffect::Breathing(BreathingEffect::Dual(color, _))
+        | ExtendedMatrixEffect::Reactive(color, _)
+        | ExtendedMatrixEffect::Starlight(StarlightEffect::Single(color), _)
+        | ExtendedMatrixEffect::Starlight(StarlightEffect::Dual(color, _), _) => Some(color),
+        ExtendedMatrixEffect::None
+        | ExtendedMatrixEffect::Breathing(BreathingEffect::Random)
+        | ExtendedMatrixEffect::Spectrum
+        | ExtendedMatrixEffect::Wave(..)
+        | ExtendedMatrixEffect::Starlight(StarlightEffect::Random, _)
+        | ExtendedMatrixEffect::Wheel(_) => None,
+    }
+}
+
+/// `effect` with the color from `effect_color` replaced, if it has one
+pub fn with_color(effect: ExtendedMatrixEffect, color: Color) -> ExtendedMatrixEffect {
+    match effect {
+        ExtendedMatrixEffect::Static(_) => ExtendedMatrixEffect::Static(color),
+        ExtendedMatrixEffect::Breathing(BreathingEffect::Single(_)) => {
+            ExtendedMatrixEffect::Breathing(BreathingEffect::Single(color))
+        }
+        ExtendedMatrixEffect::Breathing(BreathingEffect::Dual(_, color2)) => {
+            ExtendedMatrixEffect::Breathing(BreathingEffect::Dual(color, color2))
+        }
+        ExtendedMatrixEffect::Reactive(_, speed) => ExtendedMatrixEffect::Reactive(color, speed),
+        ExtendedMatrixEffect::Starlight(StarlightEffect::Single(_), speed) => {
+            ExtendedMatrixEffect::Starlight(StarlightEffect::Single(color), speed)
+        }
+        ExtendedMatrixEffect::Starlight(StarlightEffect::Dual(_, color2), speed) => {
+            ExtendedMatrixEffect::Starlight(StarlightEffect::Dual(color, color2), speed)
+        }
+        effect => effect,
+    }
+}
+
+/// Whether a wave or wheel effect runs the other way than by default, `None` for effects
+/// without a direction
+fn effect_reversed(effect: ExtendedMatrixEffect) -> Option<bool> {
+    match effect {
+        ExtendedMatrixEffect::Wave(direction, _) => Some(direction == WaveDirection::Left),
+        ExtendedMatrixEffect::Wheel(direction) => {
+            Some(direction == WheelDirection::CounterClockwise)
+        }
+        _ => None,
+    }
+}
+
+/// `effect` running the other way if `reversed`, if it has a direction
+pub fn with_reversed(effect: ExtendedMatrixEffect, reversed: bool) -> ExtendedMatrixEffect {
+    match effect {
+        ExtendedMatrixEffect::Wave(_, speed) => {
+            let direction = if reversed {
+                WaveDirection::Left
+            } else {
+                WaveDirection::Right
+            };
+            ExtendedMatrixEffect::Wave(direction, speed)
+        }
+        ExtendedMatrixEffect::Wheel(_) => {
+            let direction = if reversed {
+                WheelDirection::CounterClockwise
+            } else {
+                WheelDirection::Clockwise
+            };
+            ExtendedMatrixEffect::Wheel(direction)
+        }
+        effect => effect,
+    }
+}
+
+fn to_rgba(color: Option<Color>) -> gdk::RGBA {
+    let color = color.unwrap_or(DEFAULT_COLOR);
+    let channel = |value: u8| value as f32 / 255.;
+    gdk::RGBA::new(channel(color.r), channel(color.g), channel(color.b), 1.)
+}
+
+fn from_rgba(rgba: &gdk::RGBA) -> Color {
+    let channel = |value: f32| (value * 255.).round() as u8;
+    Color {
+        r: channel(rgba.red()),
+        g: channel(rgba.green()),
+        b: channel(rgba.blue()),
+    }
+}
@@ -1,25 +1,35 @@
 use adw::prelude::*;
-use nusb::DeviceInfo;
 use relm4::prelude::*;
 
-use driver::common::RAZER_USB_VENDOR_ID;
+use driver::{common::RAZER_USB_VENDOR_ID, devices::RazerDevice, simulator::SimulatedMouse};
 
-fn scan_devices() -> Vec<DeviceInfo> {
+/// Set to list a simulated mouse alongside the connected ones
+const SIMULATE_ENV_VAR: &str = "RUZER_SIMULATE";
+/// Set to a path to append the reports exchanged with connected mice to it as JSONL
+const RECORD_ENV_VAR: &str = "RUZER_RECORD";
+
+fn scan_devices() -> Vec<RazerDevice> {
+    let recording = std::env::var_os(RECORD_ENV_VAR);
     nusb::list_devices()
         .into_iter()
         .flatten()
         .filter(|device_info| device_info.vendor_id() == RAZER_USB_VENDOR_ID)
+        .map(RazerDevice::new)
+        .map(|device| match &recording {
+            Some(path) => device.with_recording(path),
+            None => device,
+        })
         .collect()
 }
 
 #[derive(Debug)]
 pub struct DeviceListing {
-    device: DeviceInfo,
+    device: RazerDevice,
 }
 
 #[derive(Debug)]
 pub enum HomePageOutput {
-    SelectDevice(DeviceInfo),
+    SelectDevice(RazerDevice),
 }
 
 #[relm4::factory(pub)]
@@ -28,7 +38,7 @@ impl FactoryComponent for DeviceListing {
     type CommandOutput = ();
     type Input = ();
     type Output = HomePageOutput;
-    type Init = DeviceInfo;
+    type Init = RazerDevice;
 
     fn init_model(init: Self::Init, _index: &Self::Index, _sender: FactorySender<Self>) -> Self {
         Self { device: init }
@@ -37,7 +47,7 @@ impl FactoryComponent for DeviceListing {
     view! {
         adw::ActionRow {
             set_activatable: true,
-            set_title: self.device.product_string().unwrap_or("Unknown Device"),
+            set_title: self.device.name().unwrap_or("Unknown Device"),
             connect_activated[sender, device = self.device.clone()] => move |_| {
                 sender.output(HomePageOutput::SelectDevice(device.clone())).unwrap();
             }
@@ -48,6 +58,8 @@ impl FactoryComponent for DeviceListing {
 #[derive(Debug)]
 pub struct HomePage {
     pub device_list: FactoryVecDeque<DeviceListing>,
+    /// Kept across scans so the simulated device keeps its state
+    simulated_mouse: Option<SimulatedMouse>,
 }
 
 #[derive(Debug)]
@@ -77,7 +89,12 @@ impl Component for HomePage {
             )
             .forward(sender.output_sender(), |msg| msg);
 
-        let model = Self { device_list };
+        let simulated_mouse = std::env::var_os(SIMULATE_ENV_VAR).map(|_| SimulatedMouse::default());
+
+        let model = Self {
+            device_list,
+            simulated_mouse,
+        };
         let widgets = view_output!();
         ComponentParts { model, widgets }
     }
@@ -88,10 +105,13 @@ impl Component for HomePage {
                 let mut device_list = self.device_list.guard();
                 device_list.clear();
 
-                let devices_info = scan_devices();
-                for device in devices_info {
+                let devices = scan_devices();
+                for device in devices {
                     device_list.push_back(device);
                 }
+                if let Some(simulated_mouse) = &self.simulated_mouse {
+                    device_list.push_back(RazerDevice::simulated(simulated_mouse.clone()));
+                }
             }
         }
     }
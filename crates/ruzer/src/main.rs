@@ -1,7 +1,7 @@
 use adw::prelude::*;
 use device_page::{DevicePage, DevicePageMsg};
+use driver::devices::RazerDevice;
 use home_page::{HomePage, HomePageMsg, HomePageOutput};
-use nusb::DeviceInfo;
 use relm4::prelude::*;
 
 mod device_page;
@@ -16,7 +16,7 @@ struct App {
 #[derive(Debug)]
 enum SwitchAppPage {
     Home,
-    Device(DeviceInfo),
+    Device(RazerDevice),
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -47,8 +47,8 @@ impl Component for App {
         let model = App {
             home_page: HomePage::builder().launch(()).forward(
                 sender.input_sender(),
-                |HomePageOutput::SelectDevice(device_info)| {
-                    AppMsg::SwitchPage(SwitchAppPage::Device(device_info))
+                |HomePageOutput::SelectDevice(device)| {
+                    AppMsg::SwitchPage(SwitchAppPage::Device(device))
                 },
             ),
             device_page: DevicePage::builder().launch(()).detach(),
@@ -68,9 +68,9 @@ impl Component for App {
         _root: &Self::Root,
     ) {
         match message {
-            AppMsg::SwitchPage(SwitchAppPage::Device(device_info)) => {
+            AppMsg::SwitchPage(SwitchAppPage::Device(device)) => {
                 self.current_page = AppPage::Device;
-                self.device_page.emit(DevicePageMsg::Update(device_info));
+                self.device_page.emit(DevicePageMsg::Update(device));
                 widgets.root_stack.set_visible_child_name("device");
             }
             AppMsg::SwitchPage(SwitchAppPage::Home) => {